@@ -0,0 +1,460 @@
+use core::mem::MaybeUninit;
+use core::ptr;
+use crate::eval::{Op, Prim};
+use crate::gc::GcStats;
+use crate::stack::StackRef;
+
+/*
+ * worst case value stack usage of compiled code
+ *
+ * the code is run abstractly over shapes: the size bound of every object on the stack.
+ * both sides of a branch are followed, calls are followed into the callee
+ * and at every jump target the shapes reaching it are merged slot by slot
+ * until nothing grows anymore, so loops are fine as long as they keep the depth.
+ * anything that could grow past the slots we have is rejected instead of chased.
+ *
+ * coroutine bodies run in their own carved regions and are not followed,
+ * but every spawn charges its region against the slots for as long as the machine runs,
+ * so a loop that spawns keeps growing until it is rejected.
+ * escapes end their path since they only ever shrink the stack.
+ *
+ * rebinding a name leaves junk that Pick and the other ops skip (see gc.rs),
+ * so the objects would no longer line up with the shape.
+ * every path keeps the names it bound and defining one of them again is rejected,
+ * which also catches a define in a loop or in a function called twice.
+ */
+
+/// most objects a shape can track
+const MAX_SHAPE: usize = 32;
+/// deepest call nesting followed
+const MAX_CALLS: usize = 16;
+/// most names a path can bind
+const MAX_NAMES: usize = 16;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Effect {
+	/// objects popped
+	pub args: usize,
+	/// most slots the pushed result takes, None when nothing is pushed
+	pub result: Option<usize>,
+}
+
+pub struct Limits<'l> {
+	/// slots available, usage that could go past this is an error
+	pub slots: usize,
+	/// most slots a global, native result or resumed value takes
+	pub value: usize,
+	/// value slots every spawn carves off, as given to `Machine::with_coroutines`
+	pub region: usize,
+	pub prims: &'l [(Prim,Effect)],
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum AnalysisError {
+	/// could need more than the available slots
+	Overflow,
+	/// a function calls itself so there is no static bound
+	Recursive,
+	/// a loop changes the number of objects on the stack
+	Unbalanced,
+	/// a primitive missing from the limits
+	UnknownPrim(usize),
+	/// pops more than the caller provided
+	Underflow,
+	/// ran out of scratch, shape or call nesting room
+	TooComplex,
+	/// mutates a binding in place, which can grow objects below the analysed code
+	Mutates(usize),
+	/// binds a name that may already be bound, which leaves junk the shape does not follow
+	Rebinds(usize),
+}
+
+#[derive(Clone,Copy,PartialEq)]
+struct Shape {
+	len: usize,
+	sizes: [usize;MAX_SHAPE],
+	/// slots given to spawned coroutines so far
+	carved: usize,
+	/// names bound so far
+	names: [u16;MAX_NAMES],
+	named: usize,
+}
+
+impl Shape {
+	fn slots(&self) -> usize {
+		self.sizes[..self.len].iter().sum::<usize>()+self.carved
+	}
+
+	fn top(&self,n:usize) -> Result<usize,AnalysisError>{
+		let at = self.len.checked_sub(n+1).ok_or(AnalysisError::Underflow)?;
+		Ok(self.sizes[at])
+	}
+
+	fn push(&mut self,size:usize) -> Result<(),AnalysisError>{
+		*self.sizes.get_mut(self.len).ok_or(AnalysisError::TooComplex)? = size;
+		self.len += 1;
+		Ok(())
+	}
+
+	fn pop(&mut self,n:usize) -> Result<usize,AnalysisError>{
+		let at = self.len.checked_sub(n).ok_or(AnalysisError::Underflow)?;
+		let slots = self.sizes[at..self.len].iter().sum();
+		self.len = at;
+		Ok(slots)
+	}
+
+	fn is_bound(&self,name:u16) -> bool {
+		self.names[..self.named].contains(&name)
+	}
+
+	fn bind(&mut self,name:u16) -> Result<(),AnalysisError>{
+		*self.names.get_mut(self.named).ok_or(AnalysisError::TooComplex)? = name;
+		self.named += 1;
+		Ok(())
+	}
+
+	/// grows self to cover other, returns whether anything changed
+	fn join(&mut self,other:&Shape) -> Result<bool,AnalysisError>{
+		if self.len != other.len {
+			return Err(AnalysisError::Unbalanced)
+		}
+		let mut grew = other.carved > self.carved;
+		for name in &other.names[..other.named] {
+			if !self.is_bound(*name) {
+				self.bind(*name)?;
+				grew = true;
+			}
+		}
+		self.carved = self.carved.max(other.carved);
+		for (a,b) in self.sizes[..self.len].iter_mut().zip(&other.sizes[..other.len]) {
+			if *b > *a {
+				*a = *b;
+				grew = true;
+			}
+		}
+		Ok(grew)
+	}
+}
+
+/// a shape recorded at a jump target, scratch space for the analysis
+pub struct Visit {
+	ip: usize,
+	shape: Shape,
+}
+
+struct Analyzer<'c,'l,'s> {
+	code: &'c [Op],
+	limits: &'l Limits<'l>,
+	visits: StackRef<'s, Visit>,
+	calls: [usize;MAX_CALLS],
+	depth: usize,
+	peak: usize,
+}
+
+impl Analyzer<'_,'_,'_> {
+	fn note(&mut self,slots:usize) -> Result<(),AnalysisError>{
+		if slots > self.limits.slots {
+			return Err(AnalysisError::Overflow)
+		}
+		self.peak = self.peak.max(slots);
+		Ok(())
+	}
+
+	fn is_target(&self,ip:usize) -> bool {
+		self.code.iter().any(|op| matches!(op,Op::Jump(t) | Op::JumpIfFalse(t) if *t == ip))
+	}
+
+	fn effect(&self,f:Prim) -> Option<Effect> {
+		self.limits.prims.iter().find(|(p,_)| ptr::fn_addr_eq(*p,f)).map(|(_,e)| *e)
+	}
+
+	/// follows a function from ip, visits recorded from `mark` on belong to it
+	/// returns the merged shape of all its returns
+	fn function(&mut self,ip:usize,shape:Shape) -> Result<Option<Shape>,AnalysisError>{
+		let mark = self.visits.write_index();
+		let mut ret = None;
+		let res = self.path(ip,shape,mark,&mut ret);
+		self.visits.flush(self.visits.write_index()-mark);
+		res.map(|_| ret)
+	}
+
+	fn returned(ret:&mut Option<Shape>,shape:Shape) -> Result<(),AnalysisError>{
+		match ret {
+			Some(r) => r.join(&shape).map(|_| ()),
+			None => {
+				*ret = Some(shape);
+				Ok(())
+			}
+		}
+	}
+
+	/// follows one path until it returns, halts or reaches a covered jump target
+	fn path(&mut self,mut ip:usize,mut shape:Shape,mark:usize,ret:&mut Option<Shape>) -> Result<(),AnalysisError>{
+		loop {
+			if self.is_target(ip) {
+				let (all,_) = self.visits.split();
+				match all[mark..].iter_mut().find(|v| v.ip == ip) {
+					Some(v) => {
+						if !v.shape.join(&shape)? {
+							return Ok(())
+						}
+						shape = v.shape;
+					}
+					None => {
+						self.visits.push(Visit{ip,shape}).map_err(|_| AnalysisError::TooComplex)?;
+					}
+				}
+			}
+
+			let op = *self.code.get(ip).ok_or(AnalysisError::TooComplex)?;
+			ip += 1;
+			match op {
+				Op::Push(_) => shape.push(1)?,
+				Op::Pick(n) => shape.push(shape.top(n)?)?,
+				Op::Drop => { shape.pop(1)?; }
+				Op::Swap => {
+					let (a,b) = (shape.top(0)?,shape.top(1)?);
+					shape.pop(2)?;
+					shape.push(a)?;
+					shape.push(b)?;
+				}
+				Op::List(n) => {
+					let slots = shape.pop(n)?;
+					shape.push(slots+1)?;
+				}
+				Op::Prim(f) => {
+					let e = self.effect(f).ok_or(AnalysisError::UnknownPrim(ip-1))?;
+					shape.pop(e.args)?;
+					if let Some(size) = e.result {
+						shape.push(size)?;
+					}
+				}
+				Op::Jump(to) => ip = to,
+				Op::JumpIfFalse(to) => {
+					shape.pop(1)?;
+					self.path(to,shape,mark,ret)?;
+				}
+				Op::Call(to) | Op::CallEc(to) => {
+					if self.calls[..self.depth].contains(&to) {
+						return Err(AnalysisError::Recursive)
+					}
+					*self.calls.get_mut(self.depth).ok_or(AnalysisError::TooComplex)? = to;
+					self.depth += 1;
+
+					let escape = matches!(op,Op::CallEc(_));
+					let before = shape;
+					if escape {
+						shape.push(1)?;
+						self.note(shape.slots())?;
+					}
+					let res = self.function(to,shape);
+					self.depth -= 1;
+
+					let mut after = match res? {
+						Some(after) => after,
+						None => return Ok(()),
+					};
+					if escape {
+						//escaping leaves the continuation and the value
+						let mut escaped = before;
+						escaped.push(1)?;
+						escaped.push(self.limits.value)?;
+						after.join(&escaped)?;
+					}
+					shape = after;
+				}
+				Op::Ret => return Self::returned(ret,shape),
+				Op::Escape | Op::Halt => return Ok(()),
+				Op::Define(name) => {
+					shape.top(0)?;
+					if shape.is_bound(name) {
+						return Err(AnalysisError::Rebinds(ip-1))
+					}
+					shape.bind(name)?;
+				}
+				Op::GcStats => shape.push(GcStats::SLOTS)?,
+				Op::MakeWeak => {
+					shape.top(0)?;
+					shape.push(1)?;
+				}
+				Op::WeakRef => {
+					shape.pop(1)?;
+					shape.push(self.limits.value)?;
+				}
+				Op::Get(_) => shape.push(self.limits.value)?,
+				Op::Set(_) | Op::SetCar(_) | Op::VectorSet(_) => return Err(AnalysisError::Mutates(ip-1)),
+				Op::Yield => {
+					shape.pop(1)?;
+					shape.push(self.limits.value)?;
+				}
+				Op::Expand | Op::Quasiquote => {
+					shape.pop(1)?;
+					shape.push(self.limits.value)?;
+				}
+				Op::Spawn(_) => {
+					shape.push(1)?;
+					shape.carved += self.limits.region;
+				}
+				Op::Resume => {
+					shape.pop(2)?;
+					shape.push(self.limits.value)?;
+				}
+				Op::CallNative(argc) => {
+					shape.pop(argc+1)?;
+					shape.push(self.limits.value)?;
+				}
+			}
+			self.note(shape.slots())?;
+		}
+	}
+}
+
+/// the most value stack slots running code from entry can take,
+/// counting the arguments whose sizes the caller pushes
+/// the code must not define a name that was bound before it runs
+pub fn max_stack_usage(code:&[Op],entry:usize,args:&[usize],limits:&Limits,scratch:&mut [MaybeUninit<Visit>]) -> Result<usize,AnalysisError>{
+	let mut shape = Shape{len:0,sizes:[0;MAX_SHAPE],carved:0,names:[0;MAX_NAMES],named:0};
+	for size in args {
+		shape.push(*size)?;
+	}
+
+	let mut a = Analyzer{
+		code,
+		limits,
+		visits: StackRef::from_slice(scratch),
+		calls: [0;MAX_CALLS],
+		depth: 0,
+		peak: 0,
+	};
+	a.note(shape.slots())?;
+	a.function(entry,shape)?;
+	Ok(a.peak)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::eval::{run, Exit};
+	use crate::array_stack::ArrayStack;
+	use crate::stack::make_storage;
+	use crate::testing::{add, positive, sub, SUM};
+	use crate::value::{Error, ValueStack, ValueTag::*};
+
+	const PRIMS: [(Prim,Effect);3] = [
+		(add,Effect{args:2,result:Some(1)}),
+		(sub,Effect{args:2,result:Some(1)}),
+		(positive,Effect{args:1,result:Some(1)}),
+	];
+
+	fn limits(slots:usize) -> Limits<'static> {
+		Limits{slots,value:4,region:2,prims:&PRIMS}
+	}
+
+	fn sum_in(slots:usize) -> Result<Exit,Error>{
+		let mut storage = [MaybeUninit::uninit();8];
+		let mut stack = StackRef::from_slice(&mut storage[..slots]);
+		let mut ctrl = ArrayStack::<_,4>::new();
+		let mut env = ArrayStack::<_,1>::new();
+		stack.push(Int(10)).unwrap();
+		run(&SUM,&mut stack,&mut ctrl.view(),&mut env.view(),&mut [],&mut 0,&mut 0,1000)
+	}
+
+	#[test]
+	fn bound_is_tight() {
+		let mut scratch = make_storage::<_,4>();
+		let peak = max_stack_usage(&SUM,0,&[1],&limits(16),&mut scratch).unwrap();
+		assert_eq!(peak,3);
+
+		assert_eq!(sum_in(peak),Ok(Exit::Halted));
+		assert_eq!(sum_in(peak-1),Err(Error::StackOverflow));
+		assert_eq!(max_stack_usage(&SUM,0,&[1],&limits(2),&mut scratch),Err(AnalysisError::Overflow));
+	}
+
+	#[test]
+	fn follows_calls_and_branches() {
+		//calls SUM on either a short list or a global
+		let mut code = [Op::Halt;22];
+		code[..14].copy_from_slice(&SUM);
+		code[14..].copy_from_slice(&[
+			Op::Pick(0),
+			Op::JumpIfFalse(19),
+			Op::Push(Int(1)),
+			Op::List(1),
+			Op::Jump(20),
+			//19
+			Op::Get(0),
+			//20
+			Op::Call(0),
+			Op::Halt,
+		]);
+
+		let mut scratch = make_storage::<_,4>();
+		//SUM goes up to [x n acc copy-of-n] and n is a global of at most 4 slots
+		let peak = max_stack_usage(&code,14,&[1],&limits(16),&mut scratch).unwrap();
+		assert_eq!(peak,1+4+1+4);
+	}
+
+	#[test]
+	fn rejects_unbounded_code() {
+		let mut scratch = make_storage::<_,4>();
+		let check = |code:&[Op],scratch:&mut [MaybeUninit<Visit>]| max_stack_usage(code,0,&[],&limits(16),scratch);
+
+		//pushes every time around
+		assert_eq!(check(&[Op::Push(Nil),Op::Jump(0)],&mut scratch),Err(AnalysisError::Unbalanced));
+		//keeps the depth but the list grows
+		let grow = [Op::Push(Nil),Op::Push(Nil),Op::List(2),Op::Jump(1)];
+		assert_eq!(check(&grow,&mut scratch),Err(AnalysisError::Overflow));
+		assert_eq!(check(&[Op::Call(0)],&mut scratch),Err(AnalysisError::Recursive));
+		assert_eq!(check(&[Op::Drop],&mut scratch),Err(AnalysisError::Underflow));
+
+		fn other(_:&mut ValueStack) -> Result<(),Error>{ Ok(()) }
+		assert_eq!(check(&[Op::Push(Nil),Op::Prim(other)],&mut scratch),Err(AnalysisError::UnknownPrim(1)));
+		assert_eq!(check(&[Op::Push(Nil),Op::Set(0)],&mut scratch),Err(AnalysisError::Mutates(1)));
+		//every coroutine keeps its region
+		assert_eq!(check(&[Op::Spawn(3),Op::Drop,Op::Jump(0),Op::Halt],&mut scratch),Err(AnalysisError::Overflow));
+	}
+
+	#[test]
+	fn rejects_rebinding() {
+		let mut scratch = make_storage::<_,4>();
+		let check = |code:&[Op],scratch:&mut [MaybeUninit<Visit>]| max_stack_usage(code,0,&[],&limits(16),scratch);
+
+		//the pick skips the junk and copies the list
+		let code = [
+			Op::Push(Nil),Op::Push(Nil),Op::List(2),
+			Op::Push(Int(1)),Op::Define(5),
+			Op::Push(Int(2)),Op::Define(5),
+			Op::Pick(1),
+			Op::Halt,
+		];
+		assert_eq!(check(&code,&mut scratch),Err(AnalysisError::Rebinds(6)));
+		//once per name is fine
+		let once = [Op::Push(Int(1)),Op::Define(5),Op::Push(Int(2)),Op::Define(6),Op::Halt];
+		assert_eq!(check(&once,&mut scratch),Ok(2));
+
+		//around a loop
+		let looped = [Op::Push(Nil),Op::Define(5),Op::Drop,Op::Jump(0)];
+		assert_eq!(check(&looped,&mut scratch),Err(AnalysisError::Rebinds(1)));
+		//in a function called twice
+		let twice = [Op::Call(3),Op::Call(3),Op::Halt,Op::Push(Nil),Op::Define(5),Op::Drop,Op::Ret];
+		assert_eq!(check(&twice,&mut scratch),Err(AnalysisError::Rebinds(4)));
+		//on both sides of a branch
+		let branches = [
+			Op::Push(Nil),Op::JumpIfFalse(5),
+			Op::Push(Nil),Op::Define(5),Op::Drop,
+			//5
+			Op::Push(Nil),Op::Define(5),
+			Op::Halt,
+		];
+		assert_eq!(check(&branches,&mut scratch),Err(AnalysisError::Rebinds(6)));
+	}
+
+	#[test]
+	fn charges_spawned_regions() {
+		let code = [Op::Spawn(4),Op::Spawn(4),Op::Drop,Op::Halt,Op::Ret];
+		let mut scratch = make_storage::<_,4>();
+		//both handles and two regions of two slots
+		assert_eq!(max_stack_usage(&code,0,&[],&limits(16),&mut scratch),Ok(2+2*2));
+		assert_eq!(max_stack_usage(&code,0,&[],&limits(5),&mut scratch),Err(AnalysisError::Overflow));
+	}
+}
@@ -0,0 +1,282 @@
+use core::fmt;
+use core::mem::{self, ManuallyDrop, MaybeUninit};
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::slice;
+use crate::stack::{Drain, Stack, StackRef, Window, WindowMut};
+
+/*
+ * a grow-up stack that owns its buffer
+ *
+ * there is no lifetime so it can sit in a static or a struct field without
+ * a separate make_storage array next to it. code written against StackRef
+ * (everything taking a ValueStack) runs on it through view, and anything
+ * taking its buffer as `impl Into<StackRef>` (a Machine, the symbol and native
+ * tables) can be handed the array stack itself.
+ */
+
+pub struct ArrayStack<T, const N: usize> {
+    buf: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> Drop for ArrayStack<T, N> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.live_mut() as *mut [T]) }
+    }
+}
+
+impl<T, const N: usize> Default for ArrayStack<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> ArrayStack<T, N> {
+    pub const fn new() -> Self {
+        Self{ buf: [const { MaybeUninit::uninit() }; N], len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn room_left(&self) -> usize {
+        N - self.len
+    }
+
+    fn live(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.buf.as_ptr() as *const T, self.len) }
+    }
+
+    fn live_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.buf.as_mut_ptr() as *mut T, self.len) }
+    }
+
+    /// lends the buffer out as a StackRef that owns the elements while it lives,
+    /// whatever it holds when the view is dropped comes back to this stack
+    pub fn view(&mut self) -> View<'_, T> {
+        let len = mem::take(&mut self.len);
+        let buf = self.buf.as_ptr() as *const T;
+        let mut stack = StackRef::from_slice(&mut self.buf);
+        unsafe { stack.set_write_index(len) };
+        View{ stack: ManuallyDrop::new(stack), buf, cap: N, len: &mut self.len }
+    }
+
+    /// runs one StackRef operation and keeps its length
+    fn with_ref<R>(&mut self, f: impl FnOnce(&mut StackRef<'_, T>) -> R) -> R {
+        f(&mut self.view())
+    }
+
+    pub fn push(&mut self, v: T) -> Result<(), T> { self.with_ref(|s| s.push(v)) }
+    pub fn pop(&mut self) -> Option<T> { self.with_ref(|s| s.pop()) }
+    pub fn push_n<const SIZE: usize>(&mut self, v: [T; SIZE]) -> Result<(), [T; SIZE]> { self.with_ref(|s| s.push_n(v)) }
+    pub fn pop_n<const SIZE: usize>(&mut self) -> Option<[T; SIZE]> { self.with_ref(|s| s.pop_n()) }
+    pub fn push_slice(&mut self, v: &[T]) -> Result<(), ()> where T: Clone { self.with_ref(|s| s.push_slice(v)) }
+    pub fn drop_inside(&mut self, skip: usize, count: usize) -> Result<(), ()> { self.with_ref(|s| s.drop_inside(skip, count)) }
+    pub fn insert_inside(&mut self, skip: usize, v: &[T]) -> Result<(), ()> where T: Clone { self.with_ref(|s| s.insert_inside(skip, v)) }
+    pub fn extract_inside(&mut self, skip: usize, count: usize) -> Result<(), ()> { self.with_ref(|s| s.extract_inside(skip, count)) }
+    pub fn flush(&mut self, len: usize) { self.with_ref(|s| s.flush(len)) }
+    pub fn flush_all(&mut self) { self.with_ref(|s| s.flush_all()) }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.live().last()
+    }
+
+    pub fn peek_n<const SIZE: usize>(&self) -> Option<&[T; SIZE]> {
+        self.peek_many(SIZE).map(|s| s.try_into().unwrap())
+    }
+
+    pub fn peek_many(&self, size: usize) -> Option<&[T]> {
+        let live = self.live();
+        live.get(live.len().checked_sub(size)?..)
+    }
+
+    pub fn pop_many<'b>(&'b mut self, size: usize) -> Option<Drain<'b, T>> {
+        if self.len < size {
+            return None;
+        }
+        self.len -= size;
+        unsafe { Some(Drain::new((self.buf.as_mut_ptr() as *mut T).add(self.len), size)) }
+    }
+
+    /// see StackRef::split
+    pub fn split<'b>(&'b mut self) -> (&'b mut [T], StackRef<'b, T>) {
+        let (live, free) = self.buf.split_at_mut(self.len);
+        let live = unsafe { &mut *(live as *mut [MaybeUninit<T>] as *mut [T]) };
+        (live, StackRef::from_slice(free))
+    }
+
+    /// # Safety
+    /// idx must be at most N and every slot below it must be initialized
+    pub unsafe fn set_write_index(&mut self, idx: usize) {
+        debug_assert!(idx <= N);
+        self.len = idx;
+    }
+}
+
+/// lends the whole buffer out for as long as the StackRef lives, for taking a
+/// StackRef where a make_storage buffer would go. the elements move over with it
+/// and are dropped with it, the array stack is left empty
+impl<'a, T, const N: usize> From<&'a mut ArrayStack<T, N>> for StackRef<'a, T> {
+    fn from(stack: &'a mut ArrayStack<T, N>) -> Self {
+        let len = mem::take(&mut stack.len);
+        let mut lent = StackRef::from_slice(&mut stack.buf);
+        unsafe { lent.set_write_index(len) };
+        lent
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for ArrayStack<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.live().fmt(f)
+    }
+}
+
+impl<T, const N: usize> Iterator for ArrayStack<T, N> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> { self.pop() }
+}
+
+impl<T, const N: usize> Stack<T> for ArrayStack<T, N> {
+    type Part<'b> = StackRef<'b, T> where Self: 'b;
+
+    fn push(&mut self, v: T) -> Result<(), T> { self.push(v) }
+    fn pop(&mut self) -> Option<T> { self.pop() }
+    fn peek(&self) -> Option<&T> { self.peek() }
+    fn write_index(&self) -> usize { self.len }
+    fn room_left(&self) -> usize { self.room_left() }
+    unsafe fn set_write_index(&mut self, idx: usize) { unsafe { self.set_write_index(idx) } }
+
+    fn push_n<const SIZE: usize>(&mut self, v: [T; SIZE]) -> Result<(), [T; SIZE]> { self.push_n(v) }
+    fn push_slice(&mut self, v: &[T]) -> Result<(), ()> where T: Clone { self.push_slice(v) }
+    fn pop_n<const SIZE: usize>(&mut self) -> Option<[T; SIZE]> { self.pop_n() }
+    fn pop_many<'b>(&'b mut self, size: usize) -> Option<Drain<'b, T>> { self.pop_many(size) }
+    fn peek_many<'b>(&'b self, size: usize) -> Option<Window<'b, T>> { self.peek_many(size).map(Window::new) }
+    fn drop_inside(&mut self, skip: usize, count: usize) -> Result<(), ()> { self.drop_inside(skip, count) }
+    fn insert_inside(&mut self, skip: usize, v: &[T]) -> Result<(), ()> where T: Clone { self.insert_inside(skip, v) }
+    fn extract_inside(&mut self, skip: usize, count: usize) -> Result<(), ()> { self.extract_inside(skip, count) }
+    fn flush(&mut self, len: usize) { self.flush(len) }
+
+    fn split<'b>(&'b mut self) -> (WindowMut<'b, T>, StackRef<'b, T>) {
+        let (live, part) = self.split();
+        (WindowMut::new(live), part)
+    }
+}
+
+/// an ArrayStack seen as a StackRef, see ArrayStack::view
+///
+/// the array stack is empty while the view lives. a StackRef swapped in through
+/// DerefMut is some other stack and is dropped with the view like any owner would,
+/// the one over the buffer went to whoever swapped and drops the elements
+pub struct View<'b, T> {
+    stack: ManuallyDrop<StackRef<'b, T>>,
+    buf: *const T,
+    cap: usize,
+    len: &'b mut usize,
+}
+
+impl<T> Drop for View<'_, T> {
+    fn drop(&mut self) {
+        if self.stack.is_over(self.buf, self.cap) {
+            //the values stay in the buffer so the StackRef must not drop them
+            *self.len = self.stack.write_index();
+        } else {
+            unsafe { ManuallyDrop::drop(&mut self.stack) }
+        }
+    }
+}
+
+impl<'b, T> Deref for View<'b, T> {
+    type Target = StackRef<'b, T>;
+    fn deref(&self) -> &StackRef<'b, T> {
+        &self.stack
+    }
+}
+
+impl<'b, T> DerefMut for View<'b, T> {
+    fn deref_mut(&mut self) -> &mut StackRef<'b, T> {
+        &mut self.stack
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::{ValueTag, dup_range};
+
+    static EMPTY: ArrayStack<u32, 4> = ArrayStack::new();
+
+    #[test]
+    fn owns_its_buffer() {
+        assert!(EMPTY.is_empty());
+
+        let mut stack = ArrayStack::<u32, 4>::new();
+        stack.push_slice(&[1, 2, 3]).unwrap();
+        assert!(stack.push_n([4, 5]).is_err());
+        assert_eq!(stack.peek_n(), Some(&[2, 3]));
+        stack.drop_inside(1, 1).unwrap();
+        assert_eq!(stack.peek_many(2), Some(&[1, 3][..]));
+        assert_eq!(stack.pop_many(2).map(|d| d.collect::<std::vec::Vec<_>>()), Some(std::vec![1, 3]));
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn view_keeps_changes() {
+        let mut stack = ArrayStack::<ValueTag, 8>::new();
+        stack.push_n([ValueTag::Int(1), ValueTag::Int(2)]).unwrap();
+
+        dup_range(&mut stack.view(), 0, 2).unwrap();
+        assert_eq!(stack.len(), 4);
+        assert_eq!(stack.peek_many(2), Some(&[ValueTag::Int(1), ValueTag::Int(2)][..]));
+        assert_eq!(stack.view().room_left(), 4);
+    }
+
+    #[test]
+    fn drops_what_is_left() {
+        use std::rc::Rc;
+
+        let counter = Rc::new(());
+        let mut stack = ArrayStack::<Rc<()>, 4>::new();
+        stack.push_slice(&[counter.clone(), counter.clone()]).unwrap();
+        stack.view().push(counter.clone()).unwrap();
+        assert_eq!(Rc::strong_count(&counter), 4);
+        stack.pop();
+        assert_eq!(Rc::strong_count(&counter), 3);
+        drop(stack);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn swapping_out_the_view() {
+        use std::rc::Rc;
+        use crate::stack::make_storage;
+
+        let counter = Rc::new(());
+        let mut stack = ArrayStack::<Rc<()>, 2>::new();
+        stack.push(counter.clone()).unwrap();
+
+        let mut storage = make_storage::<Rc<()>, 8>();
+        let mut other = StackRef::from_slice(&mut storage);
+        for _ in 0..8 {
+            other.push(counter.clone()).unwrap();
+        }
+        assert_eq!(Rc::strong_count(&counter), 10);
+
+        // the eight go with the view, the one on the array stack with other
+        mem::swap(&mut *stack.view(), &mut other);
+        assert_eq!(Rc::strong_count(&counter), 2);
+        assert_eq!(other.write_index(), 1);
+        drop(other);
+        assert_eq!((stack.len(), stack.peek_many(8)), (0, None));
+        assert_eq!(Rc::strong_count(&counter), 1);
+
+        stack.push(counter.clone()).unwrap();
+        drop(stack);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+}
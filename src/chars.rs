@@ -0,0 +1,218 @@
+use core::fmt;
+use crate::value::{Error, ValueStack, ValueTag};
+
+/// names accepted after `#\` besides a single literal char
+const NAMES: [(&str, char); 7] = [
+	("space", ' '),
+	("newline", '\n'),
+	("tab", '\t'),
+	("return", '\r'),
+	("nul", '\0'),
+	("alarm", '\u{7}'),
+	("delete", '\u{7f}'),
+];
+
+/// parses the text following `#\` in a char literal
+/// accepts a single char, a name like `space` or a hex scalar like `x41`
+pub fn parse_char_literal(src: &str) -> Option<char> {
+	let mut it = src.chars();
+	let first = it.next()?;
+	if it.next().is_none() {
+		return Some(first);
+	}
+
+	for (name, c) in NAMES {
+		if name == src {
+			return Some(c);
+		}
+	}
+
+	let hex = src.strip_prefix('x')?;
+	let code = u32::from_str_radix(hex, 16).ok()?;
+	char::from_u32(code)
+}
+
+/// writes c the way the reader expects it back (`#\a`, `#\space`, `#\x7`)
+pub fn write_char_literal<W: fmt::Write>(out: &mut W, c: char) -> fmt::Result {
+	for (name, n) in NAMES {
+		if n == c {
+			return write!(out, "#\\{}", name);
+		}
+	}
+
+	if c.is_control() {
+		write!(out, "#\\x{:x}", c as u32)
+	} else {
+		write!(out, "#\\{}", c)
+	}
+}
+
+fn pop_char(stack: &mut ValueStack) -> Result<char, Error> {
+	match stack.peek() {
+		None => Err(Error::StackUnderflow),
+		Some(ValueTag::Char(c)) => {
+			let c = *c;
+			stack.pop();
+			Ok(c)
+		}
+		Some(_) => Err(Error::TypeError),
+	}
+}
+
+/// (char->integer c)
+pub fn char_to_integer(stack: &mut ValueStack) -> Result<(), Error> {
+	let c = pop_char(stack)?;
+	stack.push(ValueTag::Int(c as i64)).map_err(|_| Error::StackOverflow)
+}
+
+/// (integer->char n) fails with OutOfRange on surrogates and values past 0x10FFFF
+pub fn integer_to_char(stack: &mut ValueStack) -> Result<(), Error> {
+	let n = match stack.peek() {
+		None => return Err(Error::StackUnderflow),
+		Some(ValueTag::Int(n)) => *n,
+		Some(_) => return Err(Error::TypeError),
+	};
+
+	let c = u32::try_from(n).ok()
+		.and_then(char::from_u32)
+		.ok_or(Error::OutOfRange)?;
+
+	stack.pop();
+	stack.push(ValueTag::Char(c)).map_err(|_| Error::StackOverflow)
+}
+
+/// (char-upcase c)
+/// chars whose uppercase form is more than one char are left alone
+pub fn char_upcase(stack: &mut ValueStack) -> Result<(), Error> {
+	let c = pop_char(stack)?;
+	let mut up = c.to_uppercase();
+	let ans = match (up.next(), up.next()) {
+		(Some(u), None) => u,
+		_ => c,
+	};
+	stack.push(ValueTag::Char(ans)).map_err(|_| Error::StackOverflow)
+}
+
+/// (char-alphabetic? c)
+pub fn char_is_alphabetic(stack: &mut ValueStack) -> Result<(), Error> {
+	let c = pop_char(stack)?;
+	stack.push(ValueTag::Bool(c.is_alphabetic())).map_err(|_| Error::StackOverflow)
+}
+
+/// compares the two chars on top of the stack
+/// the older one is the left hand side so `a b` computes `a op b`
+fn compare(stack: &mut ValueStack, op: fn(char, char) -> bool) -> Result<(), Error> {
+	let (a, b) = match stack.peek_n::<2>() {
+		None => return Err(Error::StackUnderflow),
+		Some([ValueTag::Char(a), ValueTag::Char(b)]) => (*a, *b),
+		Some(_) => return Err(Error::TypeError),
+	};
+
+	stack.flush(2);
+	stack.push(ValueTag::Bool(op(a, b))).map_err(|_| Error::StackOverflow)
+}
+
+/// (char=? a b)
+pub fn char_eq(stack: &mut ValueStack) -> Result<(), Error> { compare(stack, |a, b| a == b) }
+/// (char<? a b)
+pub fn char_lt(stack: &mut ValueStack) -> Result<(), Error> { compare(stack, |a, b| a < b) }
+/// (char>? a b)
+pub fn char_gt(stack: &mut ValueStack) -> Result<(), Error> { compare(stack, |a, b| a > b) }
+/// (char<=? a b)
+pub fn char_le(stack: &mut ValueStack) -> Result<(), Error> { compare(stack, |a, b| a <= b) }
+/// (char>=? a b)
+pub fn char_ge(stack: &mut ValueStack) -> Result<(), Error> { compare(stack, |a, b| a >= b) }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ValueTag::*;
+	use crate::array_stack::ArrayStack;
+	use crate::testing::Buf;
+
+	fn written(c: char) -> Buf<16> {
+		let mut buf = Buf::new();
+		write_char_literal(&mut buf, c).unwrap();
+		buf
+	}
+
+	#[test]
+	fn literal_round_trip() {
+		assert_eq!(parse_char_literal("a"), Some('a'));
+		assert_eq!(parse_char_literal("space"), Some(' '));
+		assert_eq!(parse_char_literal("x41"), Some('A'));
+		assert_eq!(parse_char_literal("λ"), Some('λ'));
+		assert_eq!(parse_char_literal("xD800"), None);
+		assert_eq!(parse_char_literal("spaces"), None);
+		assert_eq!(parse_char_literal(""), None);
+
+		for c in ['a', ' ', '\n', '\u{1}', 'λ', 'x'] {
+			let buf = written(c);
+			assert_eq!(parse_char_literal(&buf.as_str()[2..]), Some(c));
+		}
+	}
+
+	#[test]
+	fn integer_conversions() {
+		let mut stack = ArrayStack::<_, 4>::new();
+
+		stack.push(Char('A')).unwrap();
+		char_to_integer(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek(), Some(&Int(65)));
+
+		integer_to_char(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(), Some(Char('A')));
+
+		stack.push(Int(0xD800)).unwrap();
+		assert!(matches!(integer_to_char(&mut stack.view()), Err(Error::OutOfRange)));
+		assert_eq!(stack.pop(), Some(Int(0xD800)));
+
+		stack.push(Int(1)).unwrap();
+		assert!(matches!(char_to_integer(&mut stack.view()), Err(Error::TypeError)));
+		stack.pop();
+		assert!(matches!(char_to_integer(&mut stack.view()), Err(Error::StackUnderflow)));
+	}
+
+	#[test]
+	fn case_and_class() {
+		let mut stack = ArrayStack::<_, 4>::new();
+
+		stack.push(Char('q')).unwrap();
+		char_upcase(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(), Some(Char('Q')));
+
+		//ß uppercases to "SS" which is not a single char
+		stack.push(Char('ß')).unwrap();
+		char_upcase(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(), Some(Char('ß')));
+
+		stack.push(Char('λ')).unwrap();
+		char_is_alphabetic(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(), Some(Bool(true)));
+
+		stack.push(Char('7')).unwrap();
+		char_is_alphabetic(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(), Some(Bool(false)));
+	}
+
+	#[test]
+	fn comparisons() {
+		let mut stack = ArrayStack::<_, 4>::new();
+
+		stack.push_slice(&[Char('a'), Char('b')]).unwrap();
+		char_lt(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(), Some(Bool(true)));
+
+		stack.push_slice(&[Char('a'), Char('b')]).unwrap();
+		char_ge(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(), Some(Bool(false)));
+
+		stack.push_slice(&[Char('a'), Char('a')]).unwrap();
+		char_eq(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(), Some(Bool(true)));
+
+		stack.push_slice(&[Char('a'), Int(1)]).unwrap();
+		assert!(matches!(char_gt(&mut stack.view()), Err(Error::TypeError)));
+		assert_eq!(stack.len(), 2);
+	}
+}
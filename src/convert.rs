@@ -0,0 +1,228 @@
+use crate::value::{split_first, top_objects_len, Error, ValueStack, ValueTag};
+
+/*
+ * typed views of objects for natives
+ *
+ * FromValue reads one whole object, IntoValue pushes one.
+ * Args reads a fixed number of arguments at once, the first argument
+ * being the deepest, and reports which one had the wrong type.
+ *
+ * there is no string kind in the value layout yet so strings are left out
+ */
+
+/// a symbol as opposed to a plain integer
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Symbol(pub u16);
+
+pub trait FromValue<'v>: Sized {
+	/// obj is exactly one object
+	fn from_value(obj:&'v [ValueTag]) -> Option<Self>;
+}
+
+pub trait IntoValue {
+	fn push_value(self,stack:&mut ValueStack) -> Result<(),Error>;
+}
+
+fn single(obj:&[ValueTag]) -> Option<ValueTag> {
+	match obj {
+		[v] => Some(*v),
+		_ => None,
+	}
+}
+
+impl FromValue<'_> for i64 {
+	fn from_value(obj:&[ValueTag]) -> Option<Self> {
+		match single(obj)? {
+			ValueTag::Int(i) => Some(i),
+			_ => None,
+		}
+	}
+}
+
+/// integers are widened
+impl FromValue<'_> for f64 {
+	fn from_value(obj:&[ValueTag]) -> Option<Self> {
+		match single(obj)? {
+			ValueTag::Float(f) => Some(f),
+			ValueTag::Int(i) => Some(i as f64),
+			_ => None,
+		}
+	}
+}
+
+impl FromValue<'_> for bool {
+	fn from_value(obj:&[ValueTag]) -> Option<Self> {
+		match single(obj)? {
+			ValueTag::Bool(b) => Some(b),
+			_ => None,
+		}
+	}
+}
+
+impl FromValue<'_> for () {
+	fn from_value(obj:&[ValueTag]) -> Option<Self> {
+		match single(obj)? {
+			ValueTag::Nil => Some(()),
+			_ => None,
+		}
+	}
+}
+
+impl FromValue<'_> for char {
+	fn from_value(obj:&[ValueTag]) -> Option<Self> {
+		match single(obj)? {
+			ValueTag::Char(c) => Some(c),
+			_ => None,
+		}
+	}
+}
+
+impl FromValue<'_> for Symbol {
+	fn from_value(obj:&[ValueTag]) -> Option<Self> {
+		match single(obj)? {
+			ValueTag::Token(t) => Some(Symbol(t)),
+			_ => None,
+		}
+	}
+}
+
+/// any object as it sits on the stack
+impl<'v> FromValue<'v> for &'v [ValueTag] {
+	fn from_value(obj:&'v [ValueTag]) -> Option<Self> {
+		Some(obj)
+	}
+}
+
+fn push_one(stack:&mut ValueStack,v:ValueTag) -> Result<(),Error>{
+	stack.push(v).map_err(|_| Error::StackOverflow)
+}
+
+impl IntoValue for i64 {
+	fn push_value(self,stack:&mut ValueStack) -> Result<(),Error>{ push_one(stack,ValueTag::Int(self)) }
+}
+
+impl IntoValue for f64 {
+	fn push_value(self,stack:&mut ValueStack) -> Result<(),Error>{ push_one(stack,ValueTag::Float(self)) }
+}
+
+impl IntoValue for bool {
+	fn push_value(self,stack:&mut ValueStack) -> Result<(),Error>{ push_one(stack,ValueTag::Bool(self)) }
+}
+
+impl IntoValue for () {
+	fn push_value(self,stack:&mut ValueStack) -> Result<(),Error>{ push_one(stack,ValueTag::Nil) }
+}
+
+impl IntoValue for char {
+	fn push_value(self,stack:&mut ValueStack) -> Result<(),Error>{ push_one(stack,ValueTag::Char(self)) }
+}
+
+impl IntoValue for Symbol {
+	fn push_value(self,stack:&mut ValueStack) -> Result<(),Error>{ push_one(stack,ValueTag::Token(self.0)) }
+}
+
+/// copies a whole object
+impl IntoValue for &[ValueTag] {
+	fn push_value(self,stack:&mut ValueStack) -> Result<(),Error>{
+		stack.push_slice(self).map_err(|_| Error::StackOverflow)
+	}
+}
+
+/// a fixed number of typed arguments
+pub trait Args<'v>: Sized {
+	const COUNT: usize;
+	/// seq holds exactly COUNT objects
+	fn from_objects(seq:&'v [ValueTag]) -> Result<Self,Error>;
+}
+
+macro_rules! impl_args {
+	($count:expr; $($t:ident $i:expr),*) => {
+		impl<'v,$($t:FromValue<'v>),*> Args<'v> for ($($t,)*) {
+			const COUNT: usize = $count;
+
+			#[allow(unused_assignments)]
+			fn from_objects(seq:&'v [ValueTag]) -> Result<Self,Error>{
+				let mut rest = seq;
+				Ok(($({
+					let (obj,tail) = split_first(rest).ok_or(Error::StackUnderflow)?;
+					rest = tail;
+					$t::from_value(obj).ok_or(Error::ArgTypeError($i))?
+				},)*))
+			}
+		}
+	};
+}
+
+impl_args!(1; A 0);
+impl_args!(2; A 0, B 1);
+impl_args!(3; A 0, B 1, C 2);
+impl_args!(4; A 0, B 1, C 2, D 3);
+
+/// reads the top arguments without popping them, along with the slots they take
+/// use this when an argument borrows from the stack
+pub fn peek_args<'v,A:Args<'v>>(stack:&'v ValueStack) -> Result<(A,usize),Error>{
+	let len = top_objects_len(stack,A::COUNT)?;
+	let seq = stack.peek_many(len).ok_or(Error::StackUnderflow)?;
+	Ok((A::from_objects(seq)?,len))
+}
+
+/// pops the top arguments, nothing is popped if any of them does not fit
+pub fn pop_args<A>(stack:&mut ValueStack) -> Result<A,Error>
+where A: for<'v> Args<'v>
+{
+	let (args,len) = peek_args::<A>(stack)?;
+	stack.flush(len);
+	Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ValueTag::*;
+	use crate::array_stack::ArrayStack;
+
+	#[test]
+	fn pops_typed_args() {
+		let mut stack = ArrayStack::<_,16>::new();
+		stack.push_slice(&[Nil,Int(2),Float(0.5),Bool(true),Token(7)]).unwrap();
+
+		let (f,b,s) = pop_args::<(f64,bool,Symbol)>(&mut stack.view()).unwrap();
+		assert_eq!((f,b,s),(0.5,true,Symbol(7)));
+		assert_eq!(stack.len(),2);
+
+		//the first argument is the deepest, nothing is popped on failure
+		assert_eq!(pop_args::<(i64,i64)>(&mut stack.view()),Err(Error::ArgTypeError(0)));
+		assert_eq!(pop_args::<((),(),())>(&mut stack.view()),Err(Error::StackUnderflow));
+		assert_eq!(stack.len(),2);
+		assert_eq!(pop_args::<((),f64)>(&mut stack.view()),Ok(((),2.0)));
+	}
+
+	#[test]
+	fn borrowed_objects() {
+		let mut stack = ArrayStack::<_,16>::new();
+		stack.push_slice(&[Int(1),Int(2),Cons(2),Char('x')]).unwrap();
+
+		let view = stack.view();
+		let ((list,c),len) = peek_args::<(&[ValueTag],char)>(&view).unwrap();
+		assert_eq!(list,&[Int(1),Int(2),Cons(2)]);
+		assert_eq!((c,len),('x',4));
+
+		assert_eq!(peek_args::<(&[ValueTag],Symbol)>(&view).map(|(_,l)| l),Err(Error::ArgTypeError(1)));
+		assert_eq!(peek_args::<(i64,char)>(&view).map(|(_,l)| l),Err(Error::ArgTypeError(0)));
+	}
+
+	#[test]
+	fn pushes_values() {
+		let mut stack = ArrayStack::<_,8>::new();
+
+		3i64.push_value(&mut stack.view()).unwrap();
+		1.5f64.push_value(&mut stack.view()).unwrap();
+		false.push_value(&mut stack.view()).unwrap();
+		().push_value(&mut stack.view()).unwrap();
+		Symbol(9).push_value(&mut stack.view()).unwrap();
+		(&[Int(1),Cons(1)][..]).push_value(&mut stack.view()).unwrap();
+
+		assert_eq!(stack.peek_many(7),Some(&[Int(3),Float(1.5),Bool(false),Nil,Token(9),Int(1),Cons(1)][..]));
+		assert_eq!((&[Nil,Nil][..]).push_value(&mut stack.view()),Err(Error::StackOverflow));
+	}
+}
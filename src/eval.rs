@@ -0,0 +1,574 @@
+use core::mem;
+use crate::gc::{self, WeakSlot};
+use crate::mutate;
+use crate::stack::StackRef;
+use crate::value::{dup_range, objects, slide_down, swap_things, Error, ValueStack, ValueTag};
+
+/*
+ * compiled code is a plain slice of ops run against the value stack
+ * return addresses live on a separate control stack
+ *
+ * every op either finishes or fails without touching the machine,
+ * so a failed or interrupted run can always be resumed from `ip`.
+ * primitives are expected to keep the same promise:
+ * check everything before consuming their arguments.
+ *
+ * rebinding leaves junk behind (see gc.rs). before an op takes objects off the top
+ * the junk among them is squeezed out, which only moves objects down and fixes
+ * what points at them, so every op sees the stack as if the junk was never there.
+ */
+
+/// a primitive pops its arguments and pushes its result
+pub type Prim = fn(&mut ValueStack) -> Result<(),Error>;
+
+pub type ControlStack<'a> = StackRef<'a, usize>;
+
+/// global bindings as (symbol, index of the value header on the value stack)
+/// newer bindings shadow older ones
+pub type Env<'a> = StackRef<'a, (u16,usize)>;
+
+#[derive(Debug,Clone,Copy)]
+pub enum Op {
+	/// pushes a single slot constant
+	Push(ValueTag),
+	/// copies the object n objects below the top onto the top, 0 is dup
+	Pick(usize),
+	/// drops the top object
+	Drop,
+	/// swaps the top two objects
+	Swap,
+	/// gathers the top n objects into a list
+	List(usize),
+	/// a prim that fails must leave the stack as it was,
+	/// a Machine retries one that overflowed after collecting
+	Prim(Prim),
+	Jump(usize),
+	/// pops a value and jumps if it is #f
+	JumpIfFalse(usize),
+	/// pushes the return address onto the control stack and jumps
+	Call(usize),
+	/// returns to the address on the control stack or halts when it is empty
+	Ret,
+	/// call/ec: calls with an escape continuation pushed as the argument
+	/// the continuation stays under the result however the call returns,
+	/// the body must leave it in place to be able to escape
+	CallEc(usize),
+	/// pops a value and an escape continuation below it,
+	/// unwinds to the matching CallEc and returns the value from there
+	Escape,
+	/// binds the top object to a symbol, the object stays where it is
+	/// so it must not be dropped while the binding is in use.
+	/// rebinding a name leaves its old object as junk for the collector, see gc.rs
+	Define(u16),
+	/// make-weak: pushes a weak reference to the bound object on top, which stays where it is
+	MakeWeak,
+	/// weak-ref: pops a weak reference and pushes its object, or Nil once it was reclaimed
+	WeakRef,
+	/// gc-stats: pushes what the value stack holds as a list, see GcStats::push
+	GcStats,
+	/// copies the value bound to a symbol onto the top
+	Get(u16),
+	/// set!: pops the top object into the value bound to a symbol,
+	/// in place even when the value is inside another object
+	Set(u16),
+	/// set-car!: pops the top object into the first element of the bound list
+	SetCar(u16),
+	/// vector-set!: pops a value and an Int(i) below it into element i of the bound vector
+	VectorSet(u16),
+	/// pauses with the top object as the yielded value,
+	/// whatever is on top when resumed is the value of the yield
+	Yield,
+	/// evaluates the (quasiquote template) form on top,
+	/// an unquoted symbol is looked up like Get
+	Quasiquote,
+	/// expands every macro use in the form on top with the machine's macros
+	Expand,
+	/// make-coroutine: pushes a new coroutine whose body starts at the address
+	Spawn(usize),
+	/// pops a value and a coroutine below it and runs the coroutine
+	/// the value is its argument on the first resume and the value of its yield after,
+	/// whatever it yields or returns is left on top
+	Resume,
+	/// calls the native on top with the n objects below it as arguments
+	CallNative(usize),
+	Halt,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Exit {
+	Halted,
+	Yielded,
+	/// stopped without advancing at an op only a Machine can run
+	Switch,
+	/// the budget ran out before the next op, run again to resume
+	OutOfFuel,
+}
+
+/// where the object n objects below the top starts and how many slots it takes
+/// junk is not counted, the collector may take it away at any time
+fn nth_from_top(stack:&mut ValueStack,n:usize) -> Result<(usize,usize),Error>{
+	let (room,_) = stack.split();
+	let mut end = room.len();
+	let mut seen = 0;
+	for obj in objects(room) {
+		end -= obj.len();
+		if let ValueTag::Junk(_) = obj[obj.len()-1] {
+			continue
+		}
+		if seen == n {
+			return Ok((end,obj.len()))
+		}
+		seen += 1;
+	}
+	Err(Error::StackUnderflow)
+}
+
+/// how many objects off the top an op takes, moves or hands on,
+/// None for a prim which may take any of the operands
+fn takes(op:&Op) -> Option<usize> {
+	Some(match op {
+		Op::Prim(_) => return None,
+		Op::Push(_) | Op::Pick(_) | Op::Jump(_) | Op::Call(_) | Op::CallEc(_)
+			| Op::Get(_) | Op::Spawn(_) | Op::GcStats => 0,
+		Op::Swap | Op::Escape | Op::VectorSet(_) | Op::Resume => 2,
+		Op::List(n) => *n,
+		Op::CallNative(argc) => argc+1,
+		_ => 1,
+	})
+}
+
+/// whether the header at `at` is a top level object rather than inside one
+fn top_level(room:&[ValueTag],at:usize) -> bool {
+	let mut end = room.len();
+	for obj in objects(room) {
+		if end-1 == at {
+			return true
+		}
+		end -= obj.len();
+		if end <= at {
+			return false
+		}
+	}
+	false
+}
+
+pub(crate) fn lookup(env:&Env,name:u16) -> Option<usize> {
+	let all = env.peek_many(env.write_index())?;
+	all.iter().rev().find(|(n,_)| *n == name).map(|(_,at)| *at)
+}
+
+/// runs the op at `ip`
+/// `weaks` is the weak table following the objects on this stack, see gc.rs
+/// `escapes` counts the CallEc captures so far and tags each escape with it
+/// returns Some when the program halted or yielded
+pub fn step(code:&[Op],stack:&mut ValueStack,ctrl:&mut ControlStack,env:&mut Env,weaks:&mut [WeakSlot],ip:&mut usize,escapes:&mut u16) -> Result<Option<Exit>,Error>{
+	let op = *code.get(*ip).ok_or(Error::OutOfRange)?;
+	let mut next = *ip+1;
+
+	let (bindings,_) = env.split();
+	let from = match takes(&op) {
+		Some(0) => None,
+		//short of objects the op fails by itself, once no junk is left to hide behind
+		Some(n) => Some(nth_from_top(stack,n-1).map_or(0,|(at,_)| at)),
+		None => {
+			let (room,_) = stack.split();
+			Some(gc::operands(room,bindings))
+		}
+	};
+	if let Some(from) = from {
+		gc::squeeze(stack,bindings,weaks,from);
+	}
+
+	match op {
+		Op::Push(v) => stack.push(v).map_err(|_| Error::StackOverflow)?,
+		Op::Pick(n) => {
+			let (at,len) = nth_from_top(stack,n)?;
+			dup_range(stack,at,len)?;
+		}
+		Op::Drop => {
+			let size = stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+			stack.flush(size);
+		}
+		Op::Swap => {
+			nth_from_top(stack,1)?;
+			swap_things(stack).map_err(|_| Error::StackOverflow)?;
+		}
+		Op::List(n) => {
+			let slots = match n {
+				0 => 0,
+				n => {
+					let (at,_) = nth_from_top(stack,n-1)?;
+					stack.write_index()-at
+				}
+			};
+			stack.push(ValueTag::Cons(slots)).map_err(|_| Error::StackOverflow)?;
+		}
+		Op::Prim(f) => f(stack)?,
+		Op::Jump(to) => next = to,
+		Op::JumpIfFalse(to) => {
+			let v = *stack.peek().ok_or(Error::StackUnderflow)?;
+			stack.flush(v.get_size());
+			if v == ValueTag::Bool(false) {
+				next = to;
+			}
+		}
+		Op::Call(to) => {
+			ctrl.push(next).map_err(|_| Error::StackOverflow)?;
+			next = to;
+		}
+		Op::Ret => match ctrl.pop() {
+			Some(ret) => next = ret,
+			None => return Ok(Some(Exit::Halted)),
+		},
+		Op::CallEc(to) => {
+			let k = ValueTag::Escape(
+				u32::try_from(stack.write_index()).map_err(|_| Error::OutOfRange)?,
+				u32::try_from(ctrl.write_index()).map_err(|_| Error::OutOfRange)?,
+				u32::try_from(next).map_err(|_| Error::OutOfRange)?,
+				*escapes,
+			);
+			if stack.room_left() == 0 || ctrl.room_left() == 0 {
+				return Err(Error::StackOverflow)
+			}
+			stack.push(k).map_err(|_| Error::StackOverflow)?;
+			ctrl.push(next).map_err(|_| Error::StackOverflow)?;
+			*escapes = escapes.wrapping_add(1);
+			next = to;
+		}
+		Op::Escape => {
+			let size = stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+			let at = stack.write_index().checked_sub(size+1).ok_or(Error::StackUnderflow)?;
+			let (room,_) = stack.split();
+			let k = room[at];
+			let ValueTag::Escape(w,depth,ret,_) = k else {
+				return Err(Error::TypeError)
+			};
+			let (w,depth,ret) = (w as usize,depth as usize,ret as usize);
+
+			//its own slot still holds this very capture and its return address is still on the control stack
+			let frame = ctrl.peek_many(ctrl.write_index()).and_then(|c| c.get(depth));
+			if w > at || room[w] != k || frame != Some(&ret) {
+				return Err(Error::ExpiredEscape)
+			}
+
+			slide_down(stack,w+1,size);
+			ctrl.flush(ctrl.write_index()-depth);
+			next = ret;
+		}
+		Op::Define(name) => {
+			let at = stack.write_index().checked_sub(1).ok_or(Error::StackUnderflow)?;
+			let (bindings,_) = env.split();
+			match bindings.iter().rposition(|(n,_)| *n == name) {
+				Some(i) => {
+					let old = mem::replace(&mut bindings[i].1,at);
+					//nothing reads it once no name is left on or in it
+					let (room,_) = stack.split();
+					if old < at && top_level(room,old) {
+						let size = room[old].get_size();
+						if !bindings.iter().any(|(_,b)| (old+1-size..=old).contains(b)) {
+							room[old] = ValueTag::Junk(size-1);
+						}
+					}
+				}
+				None => env.push((name,at)).map_err(|_| Error::StackOverflow)?,
+			}
+		}
+		Op::Get(name) => {
+			let at = lookup(env,name).ok_or(Error::UnboundSymbol)?;
+			let (room,_) = stack.split();
+			let len = room.get(at).ok_or(Error::OutOfRange)?.get_size();
+			dup_range(stack,at+1-len,len)?;
+		}
+		Op::Set(name) | Op::SetCar(name) | Op::VectorSet(name) => {
+			let at = lookup(env,name).ok_or(Error::UnboundSymbol)?;
+			let (bindings,_) = env.split();
+			match op {
+				Op::Set(_) => mutate::replace(stack,bindings,weaks,at)?,
+				Op::SetCar(_) => {
+					let (room,_) = stack.split();
+					let car = mutate::car_of(room,at)?;
+					mutate::replace(stack,bindings,weaks,car)?
+				}
+				_ => mutate::vector_set_at(stack,bindings,weaks,at)?,
+			}
+		}
+		Op::Yield => {
+			stack.peek().ok_or(Error::StackUnderflow)?;
+			*ip = next;
+			return Ok(Some(Exit::Yielded))
+		}
+		Op::Spawn(_) | Op::Resume | Op::CallNative(_) | Op::Expand | Op::Quasiquote | Op::GcStats
+			| Op::MakeWeak | Op::WeakRef => return Ok(Some(Exit::Switch)),
+		Op::Halt => return Ok(Some(Exit::Halted)),
+	}
+
+	*ip = next;
+	Ok(None)
+}
+
+/// runs at most `fuel` ops starting at `ip`
+/// when the fuel runs out the stacks and ip hold everything needed to carry on,
+/// calling run again with the same arguments resumes exactly where it stopped
+#[allow(clippy::too_many_arguments)]
+pub fn run(code:&[Op],stack:&mut ValueStack,ctrl:&mut ControlStack,env:&mut Env,weaks:&mut [WeakSlot],ip:&mut usize,escapes:&mut u16,fuel:usize) -> Result<Exit,Error>{
+	for _ in 0..fuel {
+		if let Some(exit) = step(code,stack,ctrl,env,weaks,ip,escapes)? {
+			return Ok(exit)
+		}
+	}
+	Ok(Exit::OutOfFuel)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ValueTag::*;
+	use crate::array_stack::ArrayStack;
+	use crate::testing::{add, SUM};
+
+	#[test]
+	fn runs_to_completion() {
+		let mut stack = ArrayStack::<_,16>::new();
+		let mut ctrl = ArrayStack::<_,4>::new();
+		let mut env = ArrayStack::<_,1>::new();
+
+		stack.push(Int(10)).unwrap();
+		let mut ip = 0;
+		assert_eq!(run(&SUM,&mut stack.view(),&mut ctrl.view(),&mut env.view(),&mut [],&mut ip,&mut 0,1000).unwrap(),Exit::Halted);
+		assert_eq!(stack.pop(),Some(Int(55)));
+		assert_eq!(stack.pop(),None);
+	}
+
+	#[test]
+	fn suspends_and_resumes() {
+		//entry point at 14 calls SUM and halts
+		let mut code = [Op::Halt;16];
+		code[..14].copy_from_slice(&SUM);
+		code[14] = Op::Call(0);
+
+		let mut stack = ArrayStack::<_,16>::new();
+		let mut ctrl = ArrayStack::<_,4>::new();
+		let mut env = ArrayStack::<_,1>::new();
+
+		stack.push(Int(100)).unwrap();
+		let mut ip = 14;
+		let mut slices = 0;
+		while run(&code,&mut stack.view(),&mut ctrl.view(),&mut env.view(),&mut [],&mut ip,&mut 0,7).unwrap() == Exit::OutOfFuel {
+			slices += 1;
+			assert!(ip == 15 || ctrl.peek() == Some(&15));
+		}
+
+		assert!(slices > 100);
+		assert_eq!(ip,15);
+		assert_eq!(ctrl.pop(),None);
+		assert_eq!(stack.pop(),Some(Int(5050)));
+		assert_eq!(stack.pop(),None);
+	}
+
+	#[test]
+	fn failed_ops_can_be_retried() {
+		let code = [Op::Push(Int(1)),Op::Pick(0),Op::Prim(add),Op::Halt];
+		let mut stack = ArrayStack::<_,1>::new();
+		let mut ctrl = ArrayStack::<_,1>::new();
+		let mut env = ArrayStack::<_,1>::new();
+
+		let mut ip = 0;
+		assert!(matches!(run(&code,&mut stack.view(),&mut ctrl.view(),&mut env.view(),&mut [],&mut ip,&mut 0,10),Err(Error::StackOverflow)));
+		assert_eq!(ip,1);
+		assert_eq!(stack.peek(),Some(&Int(1)));
+
+		let mut bigger = ArrayStack::<_,2>::new();
+		bigger.push(Int(1)).unwrap();
+		assert_eq!(run(&code,&mut bigger.view(),&mut ctrl.view(),&mut env.view(),&mut [],&mut ip,&mut 0,10).unwrap(),Exit::Halted);
+		assert_eq!(bigger.pop(),Some(Int(2)));
+	}
+
+	#[test]
+	fn escapes_through_nested_calls() {
+		let code = [
+			Op::Push(Int(5)),
+			Op::CallEc(5),
+			Op::Swap,
+			Op::Drop,
+			Op::Halt,
+			//5: the body calls a helper that escapes
+			Op::Call(7),
+			Op::Ret,
+			//7
+			Op::Pick(0),
+			Op::Push(Int(42)),
+			Op::Escape,
+			Op::Ret,
+		];
+		let mut stack = ArrayStack::<_,8>::new();
+		let mut ctrl = ArrayStack::<_,4>::new();
+		let mut env = ArrayStack::<_,1>::new();
+
+		let mut ip = 0;
+		assert_eq!(run(&code,&mut stack.view(),&mut ctrl.view(),&mut env.view(),&mut [],&mut ip,&mut 0,100).unwrap(),Exit::Halted);
+		assert_eq!(ip,4);
+		assert_eq!(ctrl.pop(),None);
+		assert_eq!(stack.peek_many(2),Some(&[Int(5),Int(42)][..]));
+	}
+
+	#[test]
+	fn escape_after_return_fails() {
+		let code = [
+			//returns its own continuation
+			Op::CallEc(5),
+			Op::Swap,
+			Op::Drop,
+			Op::Push(Int(1)),
+			Op::Escape,
+			//5
+			Op::Pick(0),
+			Op::Ret,
+		];
+		let mut stack = ArrayStack::<_,8>::new();
+		let mut ctrl = ArrayStack::<_,4>::new();
+		let mut env = ArrayStack::<_,1>::new();
+
+		let mut ip = 0;
+		let err = run(&code,&mut stack.view(),&mut ctrl.view(),&mut env.view(),&mut [],&mut ip,&mut 0,100);
+		assert_eq!(err,Err(Error::ExpiredEscape));
+		assert_eq!(ip,4);
+		assert_eq!(stack.peek_many(2),Some(&[Escape(0,0,1,0),Int(1)][..]));
+	}
+
+	#[test]
+	fn escape_from_an_earlier_capture_fails() {
+		let code = [
+			Op::Push(Bool(false)),
+			Op::CallEc(7),
+			//keeps the first continuation in the slot below the next one
+			Op::Drop,
+			Op::Swap,
+			Op::Drop,
+			Op::Jump(1),
+			Op::Halt,
+			//7: returns its continuation the first time, escapes through the old one the second
+			Op::Pick(1),
+			Op::JumpIfFalse(12),
+			Op::Pick(1),
+			Op::Push(Int(7)),
+			Op::Escape,
+			//12
+			Op::Pick(0),
+			Op::Ret,
+		];
+		let mut stack = ArrayStack::<_,8>::new();
+		let mut ctrl = ArrayStack::<_,4>::new();
+		let mut env = ArrayStack::<_,1>::new();
+
+		//both captures share slot, depth and return address
+		let mut ip = 0;
+		let err = run(&code,&mut stack.view(),&mut ctrl.view(),&mut env.view(),&mut [],&mut ip,&mut 0,100);
+		assert_eq!(err,Err(Error::ExpiredEscape));
+		assert_eq!(ip,11);
+		assert_eq!(stack.peek_many(4),Some(&[Escape(1,0,2,0),Escape(1,0,2,1),Escape(1,0,2,0),Int(7)][..]));
+	}
+
+	#[test]
+	fn set_inside_a_list() {
+		let code = [
+			Op::Push(Int(1)),
+			Op::Define(1),
+			Op::Push(Int(2)),
+			Op::Define(2),
+			//both bindings now live inside the list
+			Op::List(2),
+			Op::Push(Nil),
+			Op::Push(Nil),
+			Op::List(2),
+			Op::Set(1),
+			Op::Get(2),
+			Op::Push(Char('z')),
+			Op::SetCar(1),
+			Op::Halt,
+		];
+		let mut stack = ArrayStack::<_,8>::new();
+		let mut ctrl = ArrayStack::<_,1>::new();
+		let mut env = ArrayStack::<_,2>::new();
+
+		let mut ip = 0;
+		assert_eq!(run(&code,&mut stack.view(),&mut ctrl.view(),&mut env.view(),&mut [],&mut ip,&mut 0,100),Ok(Exit::Halted));
+		assert_eq!(stack.peek_many(6),Some(&[Char('z'),Nil,Cons(2),Int(2),Cons(4),Int(2)][..]));
+		assert_eq!(env.peek_many(2),Some(&[(1,2),(2,3)][..]));
+		assert_eq!(stack.len(),6);
+	}
+
+	#[test]
+	fn rebinding_leaves_junk() {
+		let code = [
+			Op::Push(Int(5)),
+			Op::Define(2),
+			Op::Define(3),
+			Op::Push(Int(1)),
+			Op::Define(1),
+			Op::Push(Int(2)),
+			Op::Define(1),
+			//still bound as 3
+			Op::Push(Int(6)),
+			Op::Define(2),
+			//the junk is not counted
+			Op::Pick(2),
+			Op::Define(4),
+			//rebinding from inside the list leaves the list alone
+			Op::List(1),
+			Op::Define(4),
+			Op::Halt,
+		];
+		let mut stack = ArrayStack::<_,8>::new();
+		let mut ctrl = ArrayStack::<_,1>::new();
+		let mut env = ArrayStack::<_,4>::new();
+
+		let mut ip = 0;
+		assert_eq!(run(&code,&mut stack.view(),&mut ctrl.view(),&mut env.view(),&mut [],&mut ip,&mut 0,100),Ok(Exit::Halted));
+		assert_eq!(stack.peek_many(6),Some(&[Int(5),Junk(0),Int(2),Int(6),Int(5),Cons(1)][..]));
+		assert_eq!(env.peek_many(4),Some(&[(2,3),(3,0),(1,2),(4,5)][..]));
+	}
+
+	#[test]
+	fn junk_is_never_an_operand() {
+		let rebind = [Op::Push(Int(7)),Op::Push(Int(1)),Op::Define(1),Op::Push(Int(2)),Op::Define(1)];
+		let mut stack = ArrayStack::<_,8>::new();
+		let mut ctrl = ArrayStack::<_,1>::new();
+		let mut env = ArrayStack::<_,2>::new();
+
+		//the junk is squeezed out instead of ending up inside the list
+		let mut code = [Op::Halt;7];
+		code[..5].copy_from_slice(&rebind);
+		code[5] = Op::List(2);
+		assert_eq!(run(&code,&mut stack.view(),&mut ctrl.view(),&mut env.view(),&mut [],&mut 0,&mut 0,100),Ok(Exit::Halted));
+		assert_eq!(stack.len(),3);
+		assert_eq!(stack.peek_many(3),Some(&[Int(7),Int(2),Cons(2)][..]));
+		assert_eq!(env.peek_many(1),Some(&[(1,1)][..]));
+
+		//swap works on the live objects and the binding is still on a real value
+		stack.flush(3);
+		env.flush(1);
+		let mut code = [Op::Halt;8];
+		code[..5].copy_from_slice(&rebind);
+		code[5] = Op::Swap;
+		code[6] = Op::Get(1);
+		assert_eq!(run(&code,&mut stack.view(),&mut ctrl.view(),&mut env.view(),&mut [],&mut 0,&mut 0,100),Ok(Exit::Halted));
+		assert_eq!(stack.len(),3);
+		assert_eq!(stack.peek_many(3),Some(&[Int(2),Int(7),Int(7)][..]));
+		assert_eq!(env.peek_many(1),Some(&[(1,1)][..]));
+
+		//the same as without any junk
+		stack.flush(3);
+		env.flush(1);
+		let code = [Op::Push(Int(7)),Op::Push(Int(2)),Op::Define(1),Op::Swap,Op::Get(1),Op::Halt];
+		assert_eq!(run(&code,&mut stack.view(),&mut ctrl.view(),&mut env.view(),&mut [],&mut 0,&mut 0,100),Ok(Exit::Halted));
+		assert_eq!(stack.peek_many(3),Some(&[Int(2),Int(7),Int(7)][..]));
+
+		//junk is not dropped in place of a value
+		stack.flush(3);
+		env.flush(1);
+		let code = [Op::Push(Int(1)),Op::Define(1),Op::Push(Int(2)),Op::Define(1),Op::Drop,Op::Drop];
+		let mut ip = 0;
+		assert_eq!(run(&code,&mut stack.view(),&mut ctrl.view(),&mut env.view(),&mut [],&mut ip,&mut 0,100),Err(Error::StackUnderflow));
+		assert_eq!((ip,stack.len()),(5,0));
+	}
+}
@@ -0,0 +1,487 @@
+use core::ops::Range;
+use crate::value::{objects, Error, ValueStack, ValueTag};
+
+/*
+ * squashing junk out of a value stack a window at a time
+ *
+ * this is the scheme from the notes at the end of value.rs: memory is leaked
+ * willy nilly and a later scan squashes the stack down over it, fixing up what
+ * points at the objects that moved. rebinding a name (see Op::Define) turns the
+ * old object into a Junk object right where it is, nothing above it moves then.
+ *
+ * the scan covers the stack up to the object holding the highest binding.
+ * what is under a binding is only read through Get and Pick, which skip junk,
+ * but above it are the operands the code is working on and a hole between two
+ * of them would be read as one of them. before an op takes objects off the top
+ * any junk among them is squeezed out right away (see squeeze), so no op ever
+ * sees junk as a value and none of it ends up inside a list.
+ *
+ * instead of a helper stack each step moves the lowest junk up past the whole
+ * objects right over it, as many as the budget allows, and merges it with any
+ * junk it runs into. the hole is a Junk object itself, so between slices the
+ * stack is an ordinary stack the code can run on, rebind in and even set!
+ * since the next slice looks where the junk is again.
+ * once the junk reaches the top of the scanned part the operands over it slide
+ * down in one go and the slots are given back. like any object they move even
+ * over budget when nothing else moved in the slice.
+ *
+ * indices into the stack are fixed up as objects move: bindings, the own slot
+ * of escape continuations and the weak table.
+ *
+ * weak references go through a table of (generation, header index) slots so
+ * a collection never has to look for them. a slot follows its object when it
+ * moves and is cleared once its object is junk. a Weak value whose generation
+ * no longer matches its slot reads as cleared too. the table only covers the
+ * main program's stack, a coroutine can carry Weak values but not make or read them.
+ */
+
+/// a slot of a weak table, the generation goes up whenever the slot is reused
+pub type WeakSlot = (u32,Option<usize>);
+
+/// clears weak slots pointing into a range of junk
+fn clear(weaks:&mut [WeakSlot],dead:Range<usize>){
+	for (_,target) in weaks.iter_mut() {
+		if target.is_some_and(|at| dead.contains(&at)) {
+			*target = None;
+		}
+	}
+}
+
+/// points whatever pointed into `moved` `by` slots lower
+fn shift(room:&mut [ValueTag],bindings:&mut [(u16,usize)],weaks:&mut [WeakSlot],moved:Range<usize>,by:usize){
+	for (_,at) in bindings.iter_mut() {
+		if moved.contains(at) {
+			*at -= by;
+		}
+	}
+	for (_,target) in weaks.iter_mut() {
+		if let Some(at) = target && moved.contains(at) {
+			*at -= by;
+		}
+	}
+	//an escape sits at or above its own slot
+	for v in room[moved.start-by..].iter_mut() {
+		if let ValueTag::Escape(w,..) = v && moved.contains(&(*w as usize)) {
+			*w -= by as u32;
+		}
+	}
+}
+
+/// takes every top level junk object from `from` up out of the stack,
+/// sliding what is over it down, returns the slots given back
+pub(crate) fn squeeze(stack:&mut ValueStack,bindings:&mut [(u16,usize)],weaks:&mut [WeakSlot],from:usize) -> usize {
+	let mut freed = 0;
+	let mut end = stack.write_index();
+	while end > from {
+		let top = stack.write_index();
+		let (room,_) = stack.split();
+		let len = room[end-1].get_size();
+		let Some(start) = end.checked_sub(len).filter(|start| *start >= from) else {
+			//a header claiming more than there is, leave it be
+			break
+		};
+		if let ValueTag::Junk(_) = room[end-1] {
+			clear(weaks,start..end);
+			room.copy_within(end..top,start);
+			shift(room,bindings,weaks,end..top,len);
+			stack.flush(len);
+			freed += len;
+		}
+		end = start;
+	}
+	freed
+}
+
+/// where the operands start, past the top level object holding the highest binding
+pub(crate) fn operands(room:&[ValueTag],bindings:&[(u16,usize)]) -> usize {
+	let Some(highest) = bindings.iter().map(|(_,at)| *at).filter(|at| *at < room.len()).max() else {
+		return 0
+	};
+	let mut end = room.len();
+	for obj in objects(room) {
+		let start = end-obj.len();
+		if highest >= start {
+			return end
+		}
+		end = start;
+	}
+	0
+}
+
+/// the lowest top level junk object and the end of the top level object
+/// holding the highest binding, bindings past the top were unwound
+fn survey(room:&[ValueTag],bindings:&[(u16,usize)]) -> (Option<Range<usize>>,usize) {
+	let highest = bindings.iter()
+		.map(|(_,at)| *at)
+		.filter(|at| *at < room.len())
+		.max();
+	let mut end = room.len();
+	let mut junk = None;
+	let mut scanned = 0;
+	for obj in objects(room) {
+		let start = end-obj.len();
+		if let ValueTag::Junk(_) = obj[obj.len()-1] {
+			junk = Some(start..end);
+		}
+		if scanned == 0 && highest.is_some_and(|at| at >= start) {
+			scanned = end;
+		}
+		end = start;
+	}
+	(junk,scanned)
+}
+
+/// value kinds in the order of ValueTag
+pub const KINDS: usize = 17;
+
+fn kind(tag:ValueTag) -> usize {
+	match tag {
+		ValueTag::Int(_) => 0,
+		ValueTag::Float(_) => 1,
+		ValueTag::Nil => 2,
+		ValueTag::Bool(_) => 3,
+		ValueTag::Token(_) => 4,
+		ValueTag::Code(_) => 5,
+		ValueTag::Char(_) => 6,
+		ValueTag::Coroutine(_) => 7,
+		ValueTag::Escape(..) => 8,
+		ValueTag::Native(_) => 9,
+		ValueTag::Handle(..) => 10,
+		ValueTag::Cons(_) => 11,
+		ValueTag::Func(_) => 12,
+		ValueTag::Vector(_) => 13,
+		ValueTag::Table(_) => 14,
+		ValueTag::Weak(..) => 15,
+		ValueTag::Junk(_) => 16,
+	}
+}
+
+/// percent of the used stack that is junk
+fn fragmentation(room:&[ValueTag]) -> u8 {
+	let junk:usize = objects(room)
+		.filter(|obj| matches!(obj[obj.len()-1],ValueTag::Junk(_)))
+		.map(|obj| obj.len())
+		.sum();
+	match room.len() {
+		0 => 0,
+		used => (junk*100/used) as u8,
+	}
+}
+
+/// what a stack holds and what collecting it did so far
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct GcStats {
+	/// top level objects and the slots they take by the kind of their header
+	pub kinds: [(usize,usize);KINDS],
+	/// finished cycles
+	pub collections: usize,
+	pub reclaimed: usize,
+	/// the most slots a single slice moved
+	pub longest_pause: usize,
+	/// percent of the used stack that was junk when the last cycle started and when it ended
+	pub fragmentation: (u8,u8),
+}
+
+impl GcStats {
+	/// slots of the list push makes
+	pub const SLOTS: usize = 5+2*KINDS+2;
+
+	/// pushes (collections reclaimed longest-pause fragmentation-before fragmentation-after kinds)
+	/// where kinds is a list of count and slots for every kind in turn
+	pub fn push(&self,stack:&mut ValueStack) -> Result<(),Error>{
+		if stack.room_left() < Self::SLOTS {
+			return Err(Error::StackOverflow)
+		}
+		let int = |n:usize| ValueTag::Int(n as i64);
+		let (before,after) = self.fragmentation;
+		let head = [int(self.collections),int(self.reclaimed),int(self.longest_pause),int(before as usize),int(after as usize)];
+		stack.push_slice(&head).map_err(|_| Error::StackOverflow)?;
+		for (count,slots) in self.kinds {
+			stack.push_n([int(count),int(slots)]).map_err(|_| Error::StackOverflow)?;
+		}
+		stack.push_n([ValueTag::Cons(2*KINDS),ValueTag::Cons(Self::SLOTS-1)]).map_err(|_| Error::StackOverflow)
+	}
+}
+
+/// how far a collection in slices got
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct Collector {
+	busy: bool,
+	/// slots given back so far in this cycle
+	freed: usize,
+
+	collections: usize,
+	reclaimed: usize,
+	longest: usize,
+	fragmentation: (u8,u8),
+}
+
+impl Collector {
+	pub const fn new() -> Self {
+		Self{busy:false,freed:0,collections:0,reclaimed:0,longest:0,fragmentation:(0,0)}
+	}
+
+	/// whether a cycle is partway through
+	pub fn busy(&self) -> bool {
+		self.busy
+	}
+
+	fn finish(&mut self,stack:&mut ValueStack) -> usize {
+		let (room,_) = stack.split();
+		self.fragmentation.1 = fragmentation(room);
+		self.collections += 1;
+		self.reclaimed += self.freed;
+		self.busy = false;
+		core::mem::take(&mut self.freed)
+	}
+
+	/// moves objects down over junk until `budget` slots were moved,
+	/// at least one object is moved so a slice can take as long as the largest object,
+	/// or the operands over the scanned part which move all at once.
+	/// returns the slots given back when this finished the cycle
+	pub fn slice(&mut self,stack:&mut ValueStack,bindings:&mut [(u16,usize)],weaks:&mut [WeakSlot],budget:usize) -> Option<usize> {
+		if !self.busy {
+			let (room,_) = stack.split();
+			self.fragmentation.0 = fragmentation(room);
+			self.busy = true;
+		}
+		let mut moved = 0;
+		let res = loop {
+			let top = stack.write_index();
+			let (room,_) = stack.split();
+			let (junk,end) = survey(room,bindings);
+			let junk = match junk {
+				Some(junk) if junk.start <= end => junk,
+				//whatever junk is left sits among the operands
+				_ => break Some(self.finish(stack)),
+			};
+			let len = junk.len();
+			clear(weaks,junk.clone());
+
+			if junk.end >= end {
+				let above = top-junk.end;
+				if moved > 0 && moved+above > budget {
+					break None
+				}
+				room.copy_within(junk.end..top,junk.start);
+				shift(room,bindings,weaks,junk.end..top,len);
+				stack.flush(len);
+				self.freed += len;
+				moved += above;
+				continue
+			}
+
+			//the highest object boundary within budget and the lowest object over the junk
+			let left = budget.saturating_sub(moved);
+			let mut fit = None;
+			let mut lowest = end;
+			let mut hi = end;
+			for obj in objects(&room[junk.end..end]) {
+				if fit.is_none() && hi-junk.end <= left {
+					fit = Some(hi);
+				}
+				lowest = hi;
+				hi -= obj.len();
+			}
+			if hi != junk.end {
+				//a header claiming more than there is, leave it be
+				break Some(self.finish(stack))
+			}
+
+			if let ValueTag::Junk(_) = room[lowest-1] {
+				room[lowest-1] = ValueTag::Junk(lowest-junk.start-1);
+				continue
+			}
+			let to = match fit {
+				Some(to) => to,
+				None if moved == 0 => lowest,
+				None => break None,
+			};
+			room.copy_within(junk.end..to,junk.start);
+			room[to-1] = ValueTag::Junk(len-1);
+			shift(room,bindings,weaks,junk.end..to,len);
+			moved += to-junk.end;
+		};
+		self.longest = self.longest.max(moved);
+		res
+	}
+
+	/// a whole cycle at once, the rest of one partway through included
+	pub fn collect(&mut self,stack:&mut ValueStack,bindings:&mut [(u16,usize)],weaks:&mut [WeakSlot]) -> usize {
+		loop {
+			if let Some(freed) = self.slice(stack,bindings,weaks,usize::MAX) {
+				return freed
+			}
+		}
+	}
+
+	/// walks the stack object by object
+	pub fn stats(&self,stack:&ValueStack) -> GcStats {
+		let room = stack.peek_many(stack.write_index()).unwrap_or(&[]);
+		let mut kinds = [(0,0);KINDS];
+		for obj in objects(room) {
+			let (count,slots) = &mut kinds[kind(obj[obj.len()-1])];
+			*count += 1;
+			*slots += obj.len();
+		}
+		GcStats{
+			kinds,
+			collections: self.collections,
+			reclaimed: self.reclaimed,
+			longest_pause: self.longest,
+			fragmentation: self.fragmentation,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::value::ValueTag::*;
+	use crate::value::list_items;
+	use crate::array_stack::ArrayStack;
+
+	#[test]
+	fn squashes_junk() {
+		let mut stack = ArrayStack::<ValueTag,16>::new();
+		stack.push_slice(&[Int(1), Int(2),Int(3),Junk(2), Nil, Int(4),Cons(1), Int(5)]).unwrap();
+		let mut bindings = [(0,0),(1,4),(2,6)];
+
+		let mut gc = Collector::new();
+		assert_eq!(gc.collect(&mut stack.view(),&mut bindings,&mut []),3);
+		assert_eq!(stack.peek_many(5),Some(&[Int(1),Nil,Int(4),Cons(1),Int(5)][..]));
+		assert_eq!(stack.len(),5);
+		assert_eq!(bindings,[(0,0),(1,1),(2,3)]);
+
+		//nothing dead, nothing moves
+		assert_eq!(gc.collect(&mut stack.view(),&mut bindings,&mut []),0);
+		assert_eq!(bindings,[(0,0),(1,1),(2,3)]);
+		assert!(!gc.busy());
+	}
+
+	#[test]
+	fn slices_let_the_stack_change_in_between() {
+		let mut stack = ArrayStack::<ValueTag,16>::new();
+		stack.push_slice(&[Junk(0), Int(1), Junk(0), Int(5),Int(6),Cons(2), Int(8)]).unwrap();
+		let mut bindings = [(0,1),(1,5)];
+
+		//Int(1) moves down and the junk behind it merges with the next
+		let mut gc = Collector::new();
+		assert_eq!(gc.slice(&mut stack.view(),&mut bindings,&mut [],1),None);
+		assert!(gc.busy());
+		assert_eq!(stack.peek_many(7),Some(&[Int(1),Junk(0),Junk(1), Int(5),Int(6),Cons(2), Int(8)][..]));
+		assert_eq!(bindings,[(0,0),(1,5)]);
+
+		//the compacted Int(1) is rebound, it is junk for the same cycle
+		stack.push(Int(9)).unwrap();
+		stack.split().0[0] = Junk(0);
+		bindings[0].1 = 7;
+
+		//a slice always moves one object even over budget, what it leaves behind is junk
+		assert_eq!(gc.slice(&mut stack.view(),&mut bindings,&mut [],1),None);
+		assert_eq!(stack.peek_many(8),Some(&[Int(5),Int(6),Cons(2),Int(5),Int(6),Junk(2), Int(8),Int(9)][..]));
+		assert_eq!(bindings,[(0,7),(1,2)]);
+
+		assert_eq!(gc.slice(&mut stack.view(),&mut bindings,&mut [],1),None);
+		assert_eq!(bindings,[(0,7),(1,2)]);
+		//once Int(9) moved the junk is at the top of the bindings with nothing above it
+		assert_eq!(gc.slice(&mut stack.view(),&mut bindings,&mut [],1),Some(3));
+		assert_eq!(bindings,[(0,4),(1,2)]);
+		assert_eq!(stack.peek_many(5),Some(&[Int(5),Int(6),Cons(2),Int(8),Int(9)][..]));
+		assert_eq!(stack.len(),5);
+	}
+
+	#[test]
+	fn operands_move_all_at_once() {
+		let mut stack = ArrayStack::<ValueTag,16>::new();
+		stack.push_slice(&[Junk(0), Int(1), Int(2),Int(3),Cons(2)]).unwrap();
+		let mut bindings = [(0,1)];
+
+		let mut gc = Collector::new();
+		assert_eq!(gc.slice(&mut stack.view(),&mut bindings,&mut [],2),None);
+		assert_eq!(stack.peek_many(5),Some(&[Int(1),Junk(0), Int(2),Int(3),Cons(2)][..]));
+
+		//over budget, but the next slice has moved nothing yet
+		assert_eq!(gc.slice(&mut stack.view(),&mut bindings,&mut [],2),Some(1));
+		assert_eq!(stack.peek_many(4),Some(&[Int(1), Int(2),Int(3),Cons(2)][..]));
+		assert_eq!(stack.len(),4);
+		assert_eq!(gc.stats(&stack.view()).longest_pause,3);
+	}
+
+	#[test]
+	fn escapes_follow_their_slot() {
+		let mut stack = ArrayStack::<ValueTag,16>::new();
+		stack.push_slice(&[Nil,Junk(1), Escape(2,0,5,0), Int(1), Escape(2,0,5,0)]).unwrap();
+		let mut bindings = [(0,3)];
+
+		let mut gc = Collector::new();
+		assert_eq!(gc.collect(&mut stack.view(),&mut bindings,&mut []),2);
+		assert_eq!(stack.peek_many(3),Some(&[Escape(0,0,5,0),Int(1),Escape(0,0,5,0)][..]));
+		assert_eq!(bindings,[(0,1)]);
+	}
+
+	#[test]
+	fn stats_walk_the_stack() {
+		let mut stack = ArrayStack::<ValueTag,64>::new();
+		stack.push_slice(&[Junk(0), Int(1), Nil,Junk(1), Int(5),Int(6),Cons(2)]).unwrap();
+		let mut bindings = [(0,1),(1,6)];
+
+		let mut gc = Collector::new();
+		let stats = gc.stats(&stack.view());
+		assert_eq!((stats.kinds[0],stats.kinds[11],stats.kinds[16]),((1,1),(1,3),(2,3)));
+
+		//Int(1) moves down and the junk around it merges
+		assert_eq!(gc.slice(&mut stack.view(),&mut bindings,&mut [],1),None);
+		let stats = gc.stats(&stack.view());
+		assert_eq!((stats.kinds[0],stats.kinds[11],stats.kinds[16]),((1,1),(1,3),(1,3)));
+		assert_eq!(stats.kinds.iter().map(|k| k.1).sum::<usize>(),7);
+
+		assert_eq!(gc.slice(&mut stack.view(),&mut bindings,&mut [],10),Some(3));
+		let stats = gc.stats(&stack.view());
+		assert_eq!((stats.collections,stats.reclaimed,stats.longest_pause),(1,3,3));
+		assert_eq!(stats.fragmentation,(42,0));
+
+		stats.push(&mut stack.view()).unwrap();
+		let (room,_) = stack.split();
+		let report = list_items(&room[4..]).unwrap();
+		assert_eq!(report.len(),GcStats::SLOTS-1);
+		assert_eq!(report[..5],[Int(1),Int(3),Int(3),Int(42),Int(0)]);
+		assert_eq!(list_items(&report[5..]).map(|kinds| kinds[..2] == [Int(1),Int(1)]),Some(true));
+	}
+
+	#[test]
+	fn shared_bindings_count_once() {
+		let mut stack = ArrayStack::<ValueTag,4>::new();
+		stack.push(Int(1)).unwrap();
+		let mut bindings = [(0,0),(1,0)];
+
+		let mut gc = Collector::new();
+		assert_eq!(gc.collect(&mut stack.view(),&mut bindings,&mut []),0);
+		let stats = gc.stats(&stack.view());
+		assert_eq!(stats.fragmentation,(0,0));
+		assert_eq!(stats.kinds[0],(1,1));
+		assert_eq!(stack.peek_many(1),Some(&[Int(1)][..]));
+		assert_eq!(bindings,[(0,0),(1,0)]);
+	}
+
+	#[test]
+	fn weak_slots_follow_or_clear() {
+		let mut stack = ArrayStack::<ValueTag,16>::new();
+		stack.push_slice(&[Junk(0), Int(1), Junk(0), Int(5),Int(6),Cons(2)]).unwrap();
+		let mut bindings = [(0,1),(1,5)];
+		let mut weaks = [(0,Some(0)),(0,Some(1)),(3,Some(5)),(0,None)];
+
+		let mut gc = Collector::new();
+		assert_eq!(gc.slice(&mut stack.view(),&mut bindings,&mut weaks,1),None);
+		assert_eq!(weaks,[(0,None),(0,Some(0)),(3,Some(5)),(0,None)]);
+		assert_eq!(gc.slice(&mut stack.view(),&mut bindings,&mut weaks,10),Some(2));
+		assert_eq!(weaks,[(0,None),(0,Some(0)),(3,Some(3)),(0,None)]);
+
+		//a weak slot alone does not keep Int(1) alive
+		stack.split().0[0] = Junk(0);
+		bindings[0].1 = 3;
+		assert_eq!(gc.collect(&mut stack.view(),&mut bindings,&mut weaks),1);
+		assert_eq!(weaks,[(0,None),(0,None),(3,Some(2)),(0,None)]);
+		assert_eq!(bindings,[(0,2),(1,2)]);
+	}
+}
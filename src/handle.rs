@@ -0,0 +1,118 @@
+use core::fmt;
+use core::marker::PhantomData;
+use crate::convert::{FromValue, IntoValue};
+use crate::value::{Error, ValueStack, ValueTag};
+
+/*
+ * handles let scripts hold on to host resources
+ *
+ * a Handle value is a kind id and an index into a table the host manages.
+ * scripts can copy and pass them around but no op or primitive makes one
+ * out of other values, only host code pushing a typed Handle does.
+ */
+
+/// a kind of host resource, the host picks a distinct id for each
+pub trait HandleKind {
+	const ID: u16;
+	/// shown when printing, like `uart` in `#<uart 3>`
+	const NAME: &'static str;
+}
+
+/// a handle known to be of kind K
+pub struct Handle<K:HandleKind> {
+	pub index: u32,
+	_kind: PhantomData<fn() -> K>,
+}
+
+impl<K:HandleKind> Handle<K> {
+	pub fn new(index:u32) -> Self {
+		Self{index,_kind:PhantomData}
+	}
+}
+
+impl<K:HandleKind> Clone for Handle<K> {
+	fn clone(&self) -> Self { *self }
+}
+
+impl<K:HandleKind> Copy for Handle<K> {}
+
+impl<K:HandleKind> PartialEq for Handle<K> {
+	fn eq(&self,other:&Self) -> bool { self.index == other.index }
+}
+
+impl<K:HandleKind> fmt::Debug for Handle<K> {
+	fn fmt(&self,f:&mut fmt::Formatter) -> fmt::Result {
+		write_handle(f,&[(K::ID,K::NAME)],K::ID,self.index)
+	}
+}
+
+/// only matches handles of kind K
+impl<K:HandleKind> FromValue<'_> for Handle<K> {
+	fn from_value(obj:&[ValueTag]) -> Option<Self> {
+		match obj {
+			[ValueTag::Handle(kind,index)] if *kind == K::ID => Some(Self::new(*index)),
+			_ => None,
+		}
+	}
+}
+
+impl<K:HandleKind> IntoValue for Handle<K> {
+	fn push_value(self,stack:&mut ValueStack) -> Result<(),Error>{
+		stack.push(ValueTag::Handle(K::ID,self.index)).map_err(|_| Error::StackOverflow)
+	}
+}
+
+/// writes `#<name index>` looking the name up in kinds,
+/// an unknown kind shows its id instead as in `#<handle:7 3>`
+pub fn write_handle<W:fmt::Write>(out:&mut W,kinds:&[(u16,&str)],kind:u16,index:u32) -> fmt::Result {
+	match kinds.iter().find(|(id,_)| *id == kind) {
+		Some((_,name)) => write!(out,"#<{} {}>",name,index),
+		None => write!(out,"#<handle:{} {}>",kind,index),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::convert::pop_args;
+	use crate::array_stack::ArrayStack;
+	use crate::testing::Buf;
+
+	struct Uart;
+	impl HandleKind for Uart {
+		const ID: u16 = 1;
+		const NAME: &'static str = "uart";
+	}
+
+	struct Timer;
+	impl HandleKind for Timer {
+		const ID: u16 = 2;
+		const NAME: &'static str = "timer";
+	}
+
+	#[test]
+	fn typed_extraction() {
+		let mut stack = ArrayStack::<_,8>::new();
+
+		Handle::<Uart>::new(3).push_value(&mut stack.view()).unwrap();
+		Handle::<Timer>::new(3).push_value(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(2),Some(&[ValueTag::Handle(1,3),ValueTag::Handle(2,3)][..]));
+
+		assert_eq!(pop_args::<(Handle<Uart>,Handle<Uart>)>(&mut stack.view()),Err(Error::ArgTypeError(1)));
+		let (u,t) = pop_args::<(Handle<Uart>,Handle<Timer>)>(&mut stack.view()).unwrap();
+		assert_eq!((u.index,t.index),(3,3));
+
+		//plain integers are not handles
+		stack.push(ValueTag::Int(3)).unwrap();
+		assert_eq!(pop_args::<(Handle<Uart>,)>(&mut stack.view()),Err(Error::ArgTypeError(0)));
+	}
+
+	#[test]
+	fn printing() {
+		let kinds = [(Uart::ID,Uart::NAME),(Timer::ID,Timer::NAME)];
+		let mut buf = Buf::<32>::new();
+		write_handle(&mut buf,&kinds,Uart::ID,3).unwrap();
+		write_handle(&mut buf,&kinds,9,0).unwrap();
+		assert_eq!(buf.as_str(),"#<uart 3>#<handle:9 0>");
+	}
+}
@@ -1,5 +1,27 @@
 #![no_std]
+#![allow(clippy::result_unit_err, clippy::needless_lifetimes)]
+
+#[cfg(test)]
+extern crate std;
 
 pub mod stack;
 pub mod rev_stack;
-pub mod value;
\ No newline at end of file
+pub mod array_stack;
+pub mod value;
+pub mod chars;
+pub mod vector;
+pub mod table;
+pub mod mutate;
+pub mod gc;
+pub mod sym;
+pub mod macros;
+pub mod quasi;
+pub mod reader;
+pub mod eval;
+pub mod machine;
+pub mod native;
+pub mod convert;
+pub mod handle;
+pub mod analysis;
+#[cfg(test)]
+mod testing;
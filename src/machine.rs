@@ -0,0 +1,1220 @@
+use core::mem;
+use crate::eval::{self, ControlStack, Env, Exit, Op};
+use crate::gc::{Collector, GcStats, WeakSlot};
+use crate::macros::Expander;
+use crate::native::NativeTable;
+use crate::quasi;
+use crate::stack::StackRef;
+use crate::sym::SymbolTable;
+use crate::value::{dup_range, slide_down, Error, ValueStack, ValueTag};
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum State {
+	Running,
+	/// the yielded value is the top object, see `Machine::value`
+	Yielded,
+	/// the result is the top object, see `Machine::value`
+	Halted,
+	/// ip still points at the op that failed
+	Errored(Error),
+}
+
+/// when the machine collects its value stack by itself, see gc.rs.
+/// it does so one slice of `Machine::with_gc_budget` slots per step
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum GcPolicy {
+	/// only through Machine::collect and collect_slice
+	Never,
+	/// when an op finds the value stack full it waits for a cycle to finish
+	/// and is retried once, the overflow goes through when nothing came back
+	#[default]
+	OnOverflow,
+	/// also starts a cycle when an op takes the stack to at least this percentage in use
+	Threshold(u8),
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Status {
+	Suspended,
+	/// running or waiting on a coroutine it resumed
+	Running,
+	Finished,
+}
+
+/*
+ * a coroutine gets its own value and control stacks and bindings carved off the top of the main ones
+ * so overflowing them stops at its own region and never reaches anyone else's.
+ * bindings hold indices into the stack they were made on, so a coroutine defines into
+ * its own env and a Get it cannot resolve there copies from the main program's bindings.
+ *
+ * switching is a swap of registers: the coroutine's stacks and ip move into the machine
+ * and the resumer's are parked in the coroutine until it yields or returns.
+ * following the parents from the running coroutine always ends at the main program.
+ */
+
+/// the registers of a coroutine, see `Machine::with_coroutines`
+pub struct Coroutine<'a> {
+	stack: ValueStack<'a>,
+	ctrl: ControlStack<'a>,
+	env: Env<'a>,
+	ip: usize,
+	/// who resumed it while it runs, None for the main program
+	parent: Option<u16>,
+	status: Status,
+}
+
+/// rewrites a copy of the top object so a failure leaves the original as it was
+fn on_copy(stack:&mut ValueStack,f:impl FnOnce(&mut ValueStack) -> Result<(),Error>) -> Result<(),Error>{
+	let size = stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+	let top = stack.write_index();
+	dup_range(stack,top-size,size)?;
+	match f(stack) {
+		Ok(()) => {
+			let written = stack.write_index()-top;
+			slide_down(stack,top-size,written);
+			Ok(())
+		}
+		Err(e) => {
+			stack.flush(stack.write_index()-top);
+			Err(e)
+		}
+	}
+}
+
+/// the object a binding in env points at on the stack it was made on
+fn bound<'s>(env:&Env,stack:&'s ValueStack,name:u16) -> Option<&'s [ValueTag]> {
+	let at = eval::lookup(env,name)?;
+	let room = stack.peek_many(stack.write_index())?;
+	let size = room.get(at)?.get_size();
+	room.get(at+1-size..=at)
+}
+
+/// one interpreter with everything it needs in buffers it was handed
+/// several machines can live side by side over separate buffers.
+/// a buffer is a make_storage array or an ArrayStack, which can sit in a static
+pub struct Machine<'a> {
+	pub stack: ValueStack<'a>,
+	pub ctrl: ControlStack<'a>,
+	pub env: Env<'a>,
+	pub symbols: SymbolTable<'a>,
+	pub natives: NativeTable<'a>,
+	/// without any Op::Expand leaves forms as they are
+	pub macros: Option<Expander<'a>>,
+	/// what Weak values point through, see gc.rs
+	pub weaks: StackRef<'a, WeakSlot>,
+
+	code: &'a [Op],
+	entry: usize,
+	ip: usize,
+	state: State,
+	/// shared by every coroutine so no two escapes get the same tag
+	escapes: u16,
+
+	coroutines: StackRef<'a, Coroutine<'a>>,
+	/// value, control and binding slots carved for every new coroutine
+	region: (usize,usize,usize),
+	current: Option<u16>,
+	gc: Collector,
+	policy: GcPolicy,
+	/// slots a single step may move
+	budget: usize,
+}
+
+impl<'a> Machine<'a> {
+	pub fn new(
+		code:&'a [Op],
+		entry:usize,
+		stack:impl Into<ValueStack<'a>>,
+		ctrl:impl Into<ControlStack<'a>>,
+		env:impl Into<Env<'a>>,
+		symbols:SymbolTable<'a>,
+	) -> Self {
+		Self{
+			stack:stack.into(),
+			ctrl:ctrl.into(),
+			env:env.into(),
+			symbols,
+			natives:NativeTable::new(&mut []),
+			macros:None,
+			weaks:StackRef::from_slice(&mut []),
+			code,entry,ip:entry,state:State::Running,escapes:0,
+			coroutines:StackRef::from_slice(&mut []),
+			region:(0,0,0),
+			current:None,
+			gc:Collector::new(),
+			policy:GcPolicy::default(),
+			budget:32,
+		}
+	}
+
+	/// makes the natives callable, a symbol without a global binding
+	/// evaluates to the native registered under it
+	pub fn with_natives(mut self,natives:NativeTable<'a>) -> Self {
+		self.natives = natives;
+		self
+	}
+
+	/// what Op::Expand expands with
+	pub fn with_macros(mut self,macros:Expander<'a>) -> Self {
+		self.macros = Some(macros);
+		self
+	}
+
+	/// lets the code make up to pool.len() coroutines,
+	/// each one takes `stack` value slots, `ctrl` control slots and `env` bindings
+	/// off the main program's and keeps them until the machine is reset
+	pub fn with_coroutines(mut self,pool:impl Into<StackRef<'a, Coroutine<'a>>>,stack:usize,ctrl:usize,env:usize) -> Self {
+		self.coroutines = pool.into();
+		self.region = (stack,ctrl,env);
+		self
+	}
+
+	/// lets the code make weak references to bound objects, up to weaks.len() at a time
+	pub fn with_weaks(mut self,weaks:impl Into<StackRef<'a, WeakSlot>>) -> Self {
+		self.weaks = weaks.into();
+		self
+	}
+
+	/// fails with OutOfRange for a threshold over 100 percent
+	pub fn with_gc(mut self,policy:GcPolicy) -> Result<Self,Error> {
+		if let GcPolicy::Threshold(pct) = policy && pct > 100 {
+			return Err(Error::OutOfRange)
+		}
+		self.policy = policy;
+		Ok(self)
+	}
+
+	/// how many slots the machine moves in a step when it collects by itself, 32 by default
+	pub fn with_gc_budget(mut self,budget:usize) -> Self {
+		self.budget = budget;
+		self
+	}
+
+	#[inline]
+	pub fn state(&self) -> State { self.state }
+
+	#[inline]
+	pub fn ip(&self) -> usize { self.ip }
+
+	/// the yielded or returned value once the machine stopped on one
+	pub fn value(&self) -> Option<&[ValueTag]> {
+		match self.state {
+			State::Yielded | State::Halted => {
+				let size = self.stack.peek()?.get_size();
+				self.stack.peek_many(size)
+			}
+			_ => None,
+		}
+	}
+
+	/// runs a single op if the machine is running
+	pub fn step(&mut self) -> State {
+		if self.state == State::Running {
+			let before = self.stack.write_index();
+			let mut res = self.exec();
+			//a failed op changed nothing, so it can wait for the cycle and run again
+			if res == Err(Error::StackOverflow) && self.policy != GcPolicy::Never {
+				match self.collect_slice(self.budget) {
+					None => return self.state,
+					Some(0) => {}
+					Some(_) => res = self.exec(),
+				}
+			}
+			if let (Ok(_),GcPolicy::Threshold(pct)) = (res,self.policy) {
+				let after = self.stack.write_index();
+				let limit = (after+self.stack.room_left())*pct as usize;
+				if self.gc.busy() || (before*100 < limit && after*100 >= limit) {
+					self.collect_slice(self.budget);
+				}
+			}
+			self.update(res);
+		}
+		self.state
+	}
+
+	fn exec(&mut self) -> Result<Option<Exit>,Error>{
+		let weaks = match self.current {
+			None => self.weaks.split().0,
+			Some(_) => &mut [],
+		};
+		let res = eval::step(self.code,&mut self.stack,&mut self.ctrl,&mut self.env,weaks,&mut self.ip,&mut self.escapes);
+		match (res,self.current) {
+			(Ok(Some(Exit::Switch)),_) => self.switch().map(|_| None),
+			//yield has no other effect so backing up is enough to retry it
+			(Ok(Some(Exit::Yielded)),Some(k)) => self.leave(k,Status::Suspended)
+				.map(|_| None)
+				.inspect_err(|_| self.ip -= 1),
+			(Ok(Some(Exit::Halted)),Some(k)) => self.leave(k,Status::Finished).map(|_| None),
+			(Err(Error::UnboundSymbol),_) => self.get_global().map(|_| None),
+			(res,_) => res,
+		}
+	}
+
+	/// runs at most `fuel` ops, still Running afterwards means the fuel ran out
+	pub fn run(&mut self,fuel:usize) -> State {
+		for _ in 0..fuel {
+			if self.step() != State::Running {
+				break
+			}
+		}
+		self.state
+	}
+
+	fn update(&mut self,res:Result<Option<Exit>,Error>){
+		self.state = match res {
+			Ok(None) | Ok(Some(Exit::OutOfFuel)) => State::Running,
+			Ok(Some(Exit::Yielded)) => State::Yielded,
+			Ok(Some(Exit::Halted)) => State::Halted,
+			//only reachable through a code slice ending early
+			Ok(Some(Exit::Switch)) => State::Errored(Error::OutOfRange),
+			Err(e) => State::Errored(e),
+		};
+	}
+
+	fn switch(&mut self) -> Result<(),Error>{
+		match self.code.get(self.ip) {
+			Some(Op::Spawn(entry)) => {
+				self.spawn(*entry)?;
+				self.ip += 1;
+				Ok(())
+			}
+			Some(Op::Resume) => self.resume_coroutine(),
+			Some(Op::Quasiquote) => {
+				self.quasiquote()?;
+				self.ip += 1;
+				Ok(())
+			}
+			Some(Op::Expand) => {
+				self.expand()?;
+				self.ip += 1;
+				Ok(())
+			}
+			Some(Op::CallNative(argc)) => {
+				self.natives.call(&mut self.stack,*argc)?;
+				self.ip += 1;
+				Ok(())
+			}
+			Some(Op::MakeWeak) => {
+				self.make_weak()?;
+				self.ip += 1;
+				Ok(())
+			}
+			Some(Op::WeakRef) => {
+				self.weak_ref()?;
+				self.ip += 1;
+				Ok(())
+			}
+			Some(Op::GcStats) => {
+				self.gc_stats().push(&mut self.stack)?;
+				self.ip += 1;
+				Ok(())
+			}
+			_ => Err(Error::OutOfRange),
+		}
+	}
+
+	fn expand(&mut self) -> Result<(),Error>{
+		match &self.macros {
+			Some(expander) => on_copy(&mut self.stack,|stack| expander.expand(stack)),
+			None => self.stack.peek().map(|_| ()).ok_or(Error::StackUnderflow),
+		}
+	}
+
+	/// unquoted symbols are looked up like Get, other atoms stand for themselves
+	fn quasiquote(&mut self) -> Result<(),Error>{
+		let main = self.main_parked();
+		let (pool,_) = self.coroutines.split();
+		let main = main.map(|k| &pool[k as usize]);
+		let (env,natives) = (&self.env,&self.natives);
+		on_copy(&mut self.stack,|stack| quasi::quasiquote(stack,&mut |stack:&mut ValueStack| {
+			match *stack.peek().ok_or(Error::StackUnderflow)? {
+				ValueTag::Token(name) => {
+					stack.pop();
+					if let Some(at) = eval::lookup(env,name) {
+						let (room,_) = stack.split();
+						let len = room.get(at).ok_or(Error::OutOfRange)?.get_size();
+						return dup_range(stack,at+1-len,len)
+					}
+					if let Some(value) = main.and_then(|root| bound(&root.env,&root.stack,name)) {
+						return stack.push_slice(value).map_err(|_| Error::StackOverflow)
+					}
+					let id = natives.find(name).ok_or(Error::UnboundSymbol)?;
+					stack.push(ValueTag::Native(id)).map_err(|_| Error::StackOverflow)
+				}
+				v if v.get_size() == 1 => Ok(()),
+				_ => Err(Error::SyntaxError),
+			}
+		}))
+	}
+
+	/// falls back to the main program's bindings and then the natives
+	/// for a Get of an unbound symbol
+	fn get_global(&mut self) -> Result<(),Error>{
+		let Some(&Op::Get(name)) = self.code.get(self.ip) else {
+			return Err(Error::UnboundSymbol)
+		};
+		if let Some(k) = self.main_parked() {
+			let (pool,_) = self.coroutines.split();
+			let root = &pool[k as usize];
+			if let Some(value) = bound(&root.env,&root.stack,name) {
+				self.stack.push_slice(value).map_err(|_| Error::StackOverflow)?;
+				self.ip += 1;
+				return Ok(())
+			}
+		}
+		let id = self.natives.find(name).ok_or(Error::UnboundSymbol)?;
+		self.stack.push(ValueTag::Native(id)).map_err(|_| Error::StackOverflow)?;
+		self.ip += 1;
+		Ok(())
+	}
+
+	/// a slot already on the target is shared, then a cleared one is taken before the table grows
+	fn make_weak(&mut self) -> Result<(),Error>{
+		//the table only follows objects on the main program's stack
+		if self.current.is_some() {
+			return Err(Error::TypeError)
+		}
+		let at = self.stack.write_index().checked_sub(1).ok_or(Error::StackUnderflow)?;
+		//an operand goes away with the next pop, only a bound object stays put
+		let (bindings,_) = self.env.split();
+		if !bindings.iter().any(|(_,b)| *b == at) {
+			return Err(Error::TypeError)
+		}
+		if self.stack.room_left() == 0 {
+			return Err(Error::StackOverflow)
+		}
+
+		let (weaks,_) = self.weaks.split();
+		let (slot,generation) = match weaks.iter().position(|(_,target)| *target == Some(at)) {
+			Some(i) => (i,weaks[i].0),
+			None => match weaks.iter().position(|(_,target)| target.is_none()) {
+				Some(i) => {
+					let generation = weaks[i].0.wrapping_add(1);
+					weaks[i] = (generation,Some(at));
+					(i,generation)
+				}
+				None => {
+					self.weaks.push((0,Some(at))).map_err(|_| Error::CapacityError)?;
+					(self.weaks.write_index()-1,0)
+				}
+			}
+		};
+		let slot = u16::try_from(slot).map_err(|_| Error::CapacityError)?;
+		self.stack.push(ValueTag::Weak(slot,generation)).map_err(|_| Error::StackOverflow)
+	}
+
+	fn weak_ref(&mut self) -> Result<(),Error>{
+		if self.current.is_some() {
+			return Err(Error::TypeError)
+		}
+		let ValueTag::Weak(slot,generation) = *self.stack.peek().ok_or(Error::StackUnderflow)? else {
+			return Err(Error::TypeError)
+		};
+		let (weaks,_) = self.weaks.split();
+		let below = self.stack.write_index()-1;
+		let (room,_) = self.stack.split();
+		//junk that was not collected yet is gone just the same
+		let target = match weaks.get(slot as usize) {
+			Some((g,Some(at))) if *g == generation && *at < below => match room[*at] {
+				ValueTag::Junk(_) => None,
+				tag => Some((*at,tag.get_size())),
+			},
+			_ => None,
+		};
+
+		let Some((at,size)) = target else {
+			self.stack.pop();
+			return self.stack.push(ValueTag::Nil).map_err(|_| Error::StackOverflow)
+		};
+		if self.stack.room_left()+1 < size {
+			return Err(Error::StackOverflow)
+		}
+		self.stack.pop();
+		dup_range(&mut self.stack,at+1-size,size)
+	}
+
+	/// a whole collection of the running value stack, returns the slots it gave back
+	pub fn collect(&mut self) -> usize {
+		let weaks = match self.current {
+			None => self.weaks.split().0,
+			Some(_) => &mut [],
+		};
+		let (bindings,_) = self.env.split();
+		self.gc.collect(&mut self.stack,bindings,weaks)
+	}
+
+	pub fn gc_stats(&self) -> GcStats {
+		self.gc.stats(&self.stack)
+	}
+
+	/// one slice of a collection moving about `budget` slots, see gc.rs.
+	/// the machine can run in between, returns the slots given back once a cycle ends
+	pub fn collect_slice(&mut self,budget:usize) -> Option<usize> {
+		let weaks = match self.current {
+			None => self.weaks.split().0,
+			Some(_) => &mut [],
+		};
+		let (bindings,_) = self.env.split();
+		self.gc.slice(&mut self.stack,bindings,weaks,budget)
+	}
+
+	/// the coroutine the main program is parked in, None while it runs
+	fn main_parked(&mut self) -> Option<u16> {
+		let mut k = self.current?;
+		let (pool,_) = self.coroutines.split();
+		while let Some(p) = pool[k as usize].parent {
+			k = p;
+		}
+		Some(k)
+	}
+
+	/// the stacks and bindings of the main program wherever they are parked
+	fn main_stacks(&mut self) -> (&mut ValueStack<'a>,&mut ControlStack<'a>,&mut Env<'a>){
+		match self.main_parked() {
+			None => (&mut self.stack,&mut self.ctrl,&mut self.env),
+			Some(k) => {
+				let (pool,_) = self.coroutines.split();
+				let root = &mut pool[k as usize];
+				(&mut root.stack,&mut root.ctrl,&mut root.env)
+			}
+		}
+	}
+
+
+	fn spawn(&mut self,entry:usize) -> Result<(),Error>{
+		if self.coroutines.room_left() == 0 {
+			return Err(Error::CapacityError)
+		}
+		let id = u16::try_from(self.coroutines.write_index()).map_err(|_| Error::CapacityError)?;
+
+		//the handle goes first so a full main stack cannot be carved past it
+		self.stack.push(ValueTag::Coroutine(id)).map_err(|_| Error::StackOverflow)?;
+
+		let (stack_len,ctrl_len,env_len) = self.region;
+		let (main_stack,main_ctrl,main_env) = self.main_stacks();
+		let fits = main_stack.room_left() >= stack_len
+			&& main_ctrl.room_left() >= ctrl_len
+			&& main_env.room_left() >= env_len;
+		let carved = match fits {
+			true => main_stack.carve(stack_len).zip(main_ctrl.carve(ctrl_len)).zip(main_env.carve(env_len)),
+			false => None,
+		};
+		let Some(((stack,ctrl),env)) = carved else {
+			self.stack.pop();
+			return Err(Error::StackOverflow)
+		};
+
+		let co = Coroutine{stack,ctrl,env,ip:entry,parent:None,status:Status::Suspended};
+		self.coroutines.push(co).map_err(|_| Error::CapacityError)
+	}
+
+	/// trades stacks, bindings and ip with a coroutine
+	fn swap(&mut self,k:u16){
+		let (pool,_) = self.coroutines.split();
+		let co = &mut pool[k as usize];
+		mem::swap(&mut self.stack,&mut co.stack);
+		mem::swap(&mut self.ctrl,&mut co.ctrl);
+		mem::swap(&mut self.env,&mut co.env);
+		mem::swap(&mut self.ip,&mut co.ip);
+	}
+
+	fn resume_coroutine(&mut self) -> Result<(),Error>{
+		let size = self.stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+		let top = self.stack.write_index();
+		let at = top.checked_sub(size+1).ok_or(Error::StackUnderflow)?;
+
+		let (room,_) = self.stack.split();
+		let id = match room[at] {
+			ValueTag::Coroutine(id) => id,
+			_ => return Err(Error::TypeError),
+		};
+		let (pool,_) = self.coroutines.split();
+		let co = pool.get_mut(id as usize).ok_or(Error::OutOfRange)?;
+		if co.status != Status::Suspended {
+			return Err(Error::NotSuspended)
+		}
+
+		co.stack.push_slice(&room[at+1..]).map_err(|_| Error::StackOverflow)?;
+		co.status = Status::Running;
+		co.parent = self.current;
+		self.stack.flush(size+1);
+
+		self.ip += 1;
+		self.swap(id);
+		self.current = Some(id);
+		Ok(())
+	}
+
+	/// moves the top object of the running coroutine over to its resumer and switches back
+	fn leave(&mut self,k:u16,status:Status) -> Result<(),Error>{
+		let size = self.stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+		let value = self.stack.peek_many(size).ok_or(Error::StackUnderflow)?;
+
+		let (pool,_) = self.coroutines.split();
+		let co = &mut pool[k as usize];
+		co.stack.push_slice(value).map_err(|_| Error::StackOverflow)?;
+		co.status = status;
+		self.current = co.parent;
+		self.stack.flush(size);
+
+		self.swap(k);
+		Ok(())
+	}
+
+	/// carries on after a yield, or retries the failed op after an error
+	/// a halted machine stays halted
+	pub fn resume(&mut self){
+		if matches!(self.state,State::Yielded | State::Errored(_)) {
+			self.state = State::Running;
+		}
+	}
+
+	/// empties the stacks and bindings, drops every coroutine and goes back to the entry point
+	/// interned symbols are kept since the code refers to them
+	pub fn reset(&mut self){
+		while let Some(k) = self.current {
+			self.swap(k);
+			let (pool,_) = self.coroutines.split();
+			self.current = pool[k as usize].parent;
+		}
+		//carved last to first so each region sits right above what is left
+		while let Some(co) = self.coroutines.pop() {
+			let joined = self.stack.rejoin(co.stack).is_ok()
+				&& self.ctrl.rejoin(co.ctrl).is_ok()
+				&& self.env.rejoin(co.env).is_ok();
+			debug_assert!(joined);
+		}
+
+		self.stack.flush_all();
+		self.ctrl.flush_all();
+		self.env.flush_all();
+		self.weaks.flush_all();
+		self.gc = Collector::new();
+		self.ip = self.entry;
+		self.state = State::Running;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ValueTag::*;
+	use core::mem::MaybeUninit;
+	use crate::array_stack::ArrayStack;
+	use crate::stack::make_storage;
+	use crate::native::{Arity, Native};
+
+	fn inc(stack:&mut ValueStack) -> Result<(),Error>{
+		match stack.peek() {
+			Some(Int(a)) => {
+				let a = *a;
+				stack.pop();
+				stack.push(Int(a+1)).map_err(|_| Error::StackOverflow)
+			}
+			_ => Err(Error::TypeError),
+		}
+	}
+
+	/// yields a copy of the global 0 then counts up from it forever
+	const COUNTER: [Op;6] = [
+		Op::Push(Int(0)),
+		Op::Define(10),
+		Op::Get(10),
+		//loop: 3
+		Op::Yield,
+		Op::Prim(inc),
+		Op::Jump(3),
+	];
+
+	/// names and coroutines borrow from the buffers themselves,
+	/// so those two stay plain storage that never drops anything
+	struct Buffers<'s> {
+		stack: ArrayStack<ValueTag,8>,
+		ctrl: ArrayStack<usize,2>,
+		env: ArrayStack<(u16,usize),4>,
+		names: [MaybeUninit<&'s str>;8],
+		pool: [MaybeUninit<super::Coroutine<'s>>;2],
+		natives: ArrayStack<Native,2>,
+		weaks: ArrayStack<WeakSlot,2>,
+	}
+
+	impl<'s> Buffers<'s> {
+		fn new() -> Self {
+			Self{
+				stack: ArrayStack::new(),
+				ctrl: ArrayStack::new(),
+				env: ArrayStack::new(),
+				names: make_storage(),
+				pool: make_storage(),
+				natives: ArrayStack::new(),
+				weaks: ArrayStack::new(),
+			}
+		}
+
+		fn machine(&'s mut self,code:&'s [Op],entry:usize) -> Machine<'s> {
+			Machine::new(code,entry,&mut self.stack,&mut self.ctrl,&mut self.env,SymbolTable::new(&mut self.names).unwrap())
+				.with_coroutines(&mut self.pool,2,1,1)
+				.with_natives(NativeTable::new(&mut self.natives))
+				.with_weaks(&mut self.weaks)
+		}
+	}
+
+	#[test]
+	fn yields_and_resets() {
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&COUNTER,0);
+
+		assert_eq!(m.run(100),State::Yielded);
+		assert_eq!(m.value(),Some(&[Int(0)][..]));
+
+		for want in 1..4 {
+			m.resume();
+			assert_eq!(m.run(100),State::Yielded);
+			assert_eq!(m.value(),Some(&[Int(want)][..]));
+		}
+		//the global itself is untouched
+		assert_eq!(m.stack.peek_many(2),Some(&[Int(0),Int(3)][..]));
+
+		//a yielded machine does nothing until resumed
+		assert_eq!(m.step(),State::Yielded);
+
+		m.reset();
+		assert_eq!(m.stack.write_index(),0);
+		assert_eq!(m.ip(),0);
+		assert_eq!(m.run(100),State::Yielded);
+		assert_eq!(m.value(),Some(&[Int(0)][..]));
+	}
+
+	#[test]
+	fn independent_machines() {
+		let code = [Op::Push(Int(1)),Op::Prim(inc),Op::Prim(inc),Op::Halt];
+		let mut buffers = [Buffers::new(),Buffers::new()];
+		let [a,b] = &mut buffers;
+		let mut a = a.machine(&code,0);
+		let mut b = b.machine(&code,1);
+
+		b.stack.push(Int(10)).unwrap();
+		assert_eq!(a.step(),State::Running);
+		assert_eq!(b.step(),State::Running);
+		assert_eq!(a.run(10),State::Halted);
+		assert_eq!(b.run(10),State::Halted);
+
+		assert_eq!(a.value(),Some(&[Int(3)][..]));
+		assert_eq!(b.value(),Some(&[Int(12)][..]));
+	}
+
+	#[test]
+	fn errors_keep_the_failing_op() {
+		let code = [Op::Push(Nil),Op::Prim(inc),Op::Halt];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+
+		assert_eq!(m.run(10),State::Errored(Error::TypeError));
+		assert_eq!(m.ip(),1);
+		assert_eq!(m.value(),None);
+
+		//fix the argument up and retry
+		m.stack.pop();
+		m.stack.push(Int(0)).unwrap();
+		m.resume();
+		assert_eq!(m.run(10),State::Halted);
+		assert_eq!(m.value(),Some(&[Int(1)][..]));
+
+		m.resume();
+		assert_eq!(m.state(),State::Halted);
+	}
+
+	#[test]
+	fn generator() {
+		let code = [
+			Op::Spawn(7),
+			//loop: 1
+			Op::Pick(0),
+			Op::Push(Int(10)),
+			Op::Resume,
+			Op::Yield,
+			Op::Drop,
+			Op::Jump(1),
+			//yields its argument, then the next two resume values plus one and two
+			Op::Yield,
+			Op::Prim(inc),
+			Op::Yield,
+			Op::Prim(inc),
+			Op::Prim(inc),
+			Op::Ret,
+		];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+
+		for want in 10..13 {
+			assert_eq!(m.run(100),State::Yielded);
+			assert_eq!(m.value(),Some(&[Int(want)][..]));
+			m.resume();
+		}
+
+		//the arguments are left in place for a retry
+		assert_eq!(m.run(100),State::Errored(Error::NotSuspended));
+		assert_eq!(m.ip(),3);
+		assert_eq!(m.stack.peek_many(3),Some(&[Coroutine(0),Coroutine(0),Int(10)][..]));
+	}
+
+	#[test]
+	fn overflow_stays_in_the_region() {
+		let spawn3 = [Op::Spawn(0),Op::Spawn(0),Op::Spawn(0)];
+		let code = [
+			Op::Spawn(7),
+			Op::Spawn(9),
+			Op::Push(Int(7)),
+			Op::Resume,
+			Op::Drop,
+			Op::Push(Int(1)),
+			Op::Resume,
+			//7: grows past its two slots
+			Op::Pick(0),
+			Op::Pick(0),
+			//9: hands its argument straight back
+			Op::Yield,
+		];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+
+		//two regions of two slots are carved off the main stack
+		assert_eq!(m.run(5),State::Running);
+		assert_eq!(m.stack.peek_many(2),Some(&[Coroutine(0),Int(7)][..]));
+		assert_eq!(m.stack.room_left(),2);
+
+		assert_eq!(m.run(100),State::Errored(Error::StackOverflow));
+		assert_eq!(m.ip(),8);
+		assert_eq!(m.stack.peek_many(2),Some(&[Int(1),Int(1)][..]));
+
+		//a third coroutine does not fit in the pool
+		m.reset();
+		assert_eq!(m.stack.room_left(),8);
+		let mut m = Machine{code:&spawn3,..m};
+		assert_eq!(m.run(10),State::Errored(Error::CapacityError));
+		assert_eq!(m.stack.write_index(),2);
+	}
+
+	#[test]
+	fn coroutines_see_main_bindings() {
+		let code = [
+			Op::Push(Int(42)),
+			Op::Define(5),
+			Op::Spawn(6),
+			Op::Push(Nil),
+			Op::Resume,
+			Op::Halt,
+			//6: its own first slot is bound to 6, main's first slot to 5
+			Op::Drop,
+			Op::Push(Int(7)),
+			Op::Define(6),
+			Op::Get(5),
+			Op::Yield,
+		];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+
+		assert_eq!(m.run(100),State::Halted);
+		assert_eq!(m.value(),Some(&[Int(42)][..]));
+		assert_eq!(m.stack.peek_many(2),Some(&[Int(42),Int(42)][..]));
+		//the coroutine's binding stayed in its own env
+		assert_eq!(eval::lookup(&m.env,6),None);
+	}
+
+	#[test]
+	fn calls_natives_by_name() {
+		fn read_sensor(stack:&mut ValueStack,_:usize) -> Result<(),Error>{
+			match stack.peek() {
+				Some(Int(ch)) => {
+					let v = Int(100+*ch);
+					stack.pop();
+					stack.push(v).map_err(|_| Error::StackOverflow)
+				}
+				_ => Err(Error::TypeError),
+			}
+		}
+
+		let mut code = [
+			Op::Push(Int(3)),
+			Op::Get(0),
+			Op::CallNative(1),
+			Op::Halt,
+			Op::Push(Int(3)),
+			Op::Push(Int(4)),
+			Op::Get(0),
+			Op::CallNative(2),
+		];
+		let mut mem = ArrayStack::<_,1>::new();
+		let mut names = make_storage::<_,8>();
+		let mut symbols = SymbolTable::new(&mut names).unwrap();
+		let mut natives = NativeTable::new(&mut mem);
+		natives.register(&mut symbols,"read-sensor",Arity::Exactly(1),read_sensor).unwrap();
+		let name = symbols.find("read-sensor").unwrap();
+		for op in code.iter_mut() {
+			if let Op::Get(n) = op {
+				*n = name;
+			}
+		}
+
+		let mut stack = ArrayStack::<_,8>::new();
+		let mut ctrl = ArrayStack::<_,1>::new();
+		let mut env = ArrayStack::<_,1>::new();
+		let mut m = Machine::new(
+			&code,
+			0,
+			&mut stack,
+			&mut ctrl,
+			&mut env,
+			symbols,
+		).with_natives(natives);
+
+		assert_eq!(m.run(10),State::Halted);
+		assert_eq!(m.value(),Some(&[Int(103)][..]));
+
+		m.reset();
+		m.ip = 4;
+		assert_eq!(m.run(10),State::Errored(Error::ArityError));
+		assert_eq!(m.ip(),7);
+		assert_eq!(m.stack.peek_many(3),Some(&[Int(3),Int(4),Native(0)][..]));
+	}
+
+	#[test]
+	fn rebound_bindings_are_collected() {
+		let code = [
+			Op::Push(Int(1)),
+			Op::Define(10),
+			Op::Push(Int(2)),
+			Op::Push(Int(3)),
+			Op::List(2),
+			Op::Define(10),
+			Op::Get(10),
+			Op::Yield,
+			Op::Drop,
+			Op::Get(10),
+			Op::Halt,
+		];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+
+		assert_eq!(m.run(100),State::Yielded);
+		assert_eq!(m.stack.peek_many(7),Some(&[Junk(0),Int(2),Int(3),Cons(2),Int(2),Int(3),Cons(2)][..]));
+		let stats = m.gc_stats();
+		assert_eq!((stats.kinds[11],stats.kinds[16]),((2,6),(1,1)));
+
+		//the bound list moves down, the copy on top is over budget
+		assert_eq!(m.collect_slice(1),None);
+		assert_eq!(m.stack.peek_many(7),Some(&[Int(2),Int(3),Cons(2),Junk(0),Int(2),Int(3),Cons(2)][..]));
+
+		m.resume();
+		assert_eq!(m.run(100),State::Halted);
+		assert_eq!(m.value(),Some(&[Int(2),Int(3),Cons(2)][..]));
+		assert_eq!(m.collect(),1);
+		assert_eq!(m.stack.write_index(),6);
+		assert_eq!(m.collect(),0);
+
+		let stats = m.gc_stats();
+		assert_eq!((stats.collections,stats.reclaimed),(2,1));
+		assert_eq!(stats.kinds[11],(2,6));
+	}
+
+	/// rebinds a two element list forever, each one is junk after the next
+	const CHURN: [Op;5] = [
+		Op::Push(Int(1)),
+		Op::Push(Int(2)),
+		Op::List(2),
+		Op::Define(10),
+		Op::Jump(0),
+	];
+
+	#[test]
+	fn full_stack_collects_and_retries() {
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&CHURN,0).with_gc(GcPolicy::Never).unwrap();
+		assert_eq!(m.run(100),State::Errored(Error::StackOverflow));
+		assert_eq!(m.ip(),2);
+		assert_eq!(m.stack.write_index(),8);
+
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&CHURN,0);
+		assert_eq!(m.run(100),State::Running);
+		assert!(m.stack.write_index() <= 8);
+
+		//a live stack has nothing to give back so the overflow goes through
+		let code = [
+			Op::Push(Int(1)),Op::Push(Int(2)),Op::Push(Int(3)),Op::List(3),Op::Define(10),
+			Op::Push(Int(1)),Op::Push(Int(2)),Op::Push(Int(3)),Op::List(3),Op::Define(11),
+			Op::Push(Nil),
+		];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+		assert_eq!(m.run(100),State::Errored(Error::StackOverflow));
+		assert_eq!(m.ip(),10);
+		assert_eq!(m.stack.peek(),Some(&Cons(3)));
+	}
+
+	#[test]
+	fn overflow_waits_for_the_cycle() {
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&CHURN,0).with_gc_budget(1);
+		assert_eq!(m.run(12),State::Running);
+		assert_eq!(m.stack.peek_many(8),Some(&[Int(1),Int(2),Junk(2),Int(1),Int(2),Cons(2),Int(1),Int(2)][..]));
+
+		//the list moves down, the operands over it are the next slice
+		assert_eq!(m.step(),State::Running);
+		assert_eq!(m.ip(),2);
+		assert_eq!(m.stack.peek_many(8),Some(&[Int(1),Int(2),Cons(2),Int(1),Int(2),Junk(2),Int(1),Int(2)][..]));
+
+		assert_eq!(m.step(),State::Running);
+		assert_eq!(m.ip(),3);
+		assert_eq!(m.stack.peek_many(6),Some(&[Int(1),Int(2),Cons(2),Int(1),Int(2),Cons(2)][..]));
+		assert_eq!(m.gc_stats().longest_pause,3);
+	}
+
+	#[test]
+	fn threshold_collects_early() {
+		//Int(1) is junk before the stack gets to half full
+		let code = [
+			Op::Push(Int(1)),Op::Define(10),
+			Op::Push(Int(2)),Op::Define(10),
+			Op::Push(Int(3)),Op::Push(Int(4)),
+			Op::Halt,
+		];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+		assert_eq!(m.run(10),State::Halted);
+		assert_eq!(m.stack.write_index(),4);
+
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0).with_gc(GcPolicy::Threshold(50)).unwrap();
+		assert_eq!(m.run(10),State::Halted);
+		assert_eq!(m.stack.peek_many(3),Some(&[Int(2),Int(3),Int(4)][..]));
+		assert_eq!(m.stack.write_index(),3);
+
+		//staying above the threshold does not start another cycle
+		let code = [Op::Push(Nil),Op::Push(Nil),Op::Push(Nil),Op::Push(Nil),Op::Push(Nil),Op::Push(Nil),Op::Halt];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0).with_gc(GcPolicy::Threshold(50)).unwrap();
+		assert_eq!(m.run(10),State::Halted);
+		assert_eq!(m.gc_stats().collections,1);
+
+		let mut buffers = Buffers::new();
+		assert!(buffers.machine(&code,0).with_gc(GcPolicy::Threshold(101)).is_err());
+	}
+
+	#[test]
+	fn gc_stats_as_a_list() {
+		let code = [Op::GcStats,Op::Halt];
+		let mut stack = ArrayStack::<_,{GcStats::SLOTS}>::new();
+		let mut names = make_storage::<_,8>();
+		let mut m = Machine::new(
+			&code,
+			0,
+			&mut stack,
+			StackRef::from_slice(&mut []),
+			StackRef::from_slice(&mut []),
+			SymbolTable::new(&mut names).unwrap(),
+		);
+		assert_eq!(m.run(10),State::Halted);
+		assert_eq!(m.value().map(|v| v.len()),Some(GcStats::SLOTS));
+		assert_eq!(m.value().map(|v| v[..5] == [Int(0);5]),Some(true));
+
+		//no room for it is an ordinary overflow
+		m.reset();
+		m.stack.push(Nil).unwrap();
+		assert_eq!(m.run(10),State::Errored(Error::StackOverflow));
+	}
+
+	#[test]
+	fn weak_refs_break_when_reclaimed() {
+		let code = [
+			Op::Push(Int(7)),
+			Op::Define(10),
+			Op::MakeWeak,
+			Op::Pick(0),
+			Op::WeakRef,
+			Op::Yield,
+			Op::Drop,
+			Op::Push(Nil),
+			Op::Define(10),
+			Op::Pick(1),
+			Op::WeakRef,
+			Op::Yield,
+			Op::Drop,
+			Op::Pick(1),
+			Op::WeakRef,
+			Op::Halt,
+		];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+
+		assert_eq!(m.run(100),State::Yielded);
+		assert_eq!(m.stack.peek_many(2),Some(&[Weak(0,0),Int(7)][..]));
+
+		//rebound, junk reads as gone before it is collected
+		m.resume();
+		assert_eq!(m.run(100),State::Yielded);
+		assert_eq!(m.value(),Some(&[Nil][..]));
+
+		assert_eq!(m.collect(),1);
+		assert_eq!(m.weaks.peek(),Some(&(0,None)));
+		m.resume();
+		assert_eq!(m.run(100),State::Halted);
+		assert_eq!(m.value(),Some(&[Nil][..]));
+		assert_eq!(m.stack.write_index(),3);
+
+		//the cleared slot is reused under a new generation
+		let code = [Op::MakeWeak,Op::Halt];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+		m.stack.push(Int(1)).unwrap();
+		m.env.push((10,0)).unwrap();
+		m.weaks.push((4,None)).unwrap();
+		assert_eq!(m.run(10),State::Halted);
+		assert_eq!(m.value(),Some(&[Weak(0,5)][..]));
+
+		//an operand has no binding to keep it in place
+		let code = [Op::Push(Int(1)),Op::MakeWeak];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+		assert_eq!(m.run(10),State::Errored(Error::TypeError));
+
+		//making it again shares the slot, two slots are plenty
+		let code = [
+			Op::Push(Int(7)),
+			Op::Define(10),
+			Op::MakeWeak,
+			Op::Drop,
+			Op::MakeWeak,
+			Op::Drop,
+			Op::MakeWeak,
+			Op::Halt,
+		];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+		assert_eq!(m.run(100),State::Halted);
+		assert_eq!(m.value(),Some(&[Weak(0,0)][..]));
+		assert_eq!(m.weaks.write_index(),1);
+
+		let code = [Op::Push(Int(1)),Op::WeakRef];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+		assert_eq!(m.run(10),State::Errored(Error::TypeError));
+	}
+
+	#[test]
+	fn weak_refs_follow_set() {
+		//the list moves the bound 5 up two slots
+		let code = [
+			Op::Push(Int(1)),
+			Op::Define(10),
+			Op::Push(Int(5)),
+			Op::Define(11),
+			Op::MakeWeak,
+			Op::Push(Nil),
+			Op::Push(Nil),
+			Op::List(2),
+			Op::Set(10),
+			Op::WeakRef,
+			Op::Halt,
+		];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+		assert_eq!(m.run(100),State::Halted);
+		assert_eq!(m.value(),Some(&[Int(5)][..]));
+		assert_eq!(m.weaks.peek(),Some(&(0,Some(3))));
+
+		//the old value is gone even though the new one sits in its slot
+		let code = [
+			Op::Push(Int(1)),
+			Op::Define(10),
+			Op::MakeWeak,
+			Op::Push(Int(9)),
+			Op::Set(10),
+			Op::WeakRef,
+			Op::Halt,
+		];
+		let mut buffers = Buffers::new();
+		let mut m = buffers.machine(&code,0);
+		assert_eq!(m.run(100),State::Halted);
+		assert_eq!(m.value(),Some(&[Nil][..]));
+		assert_eq!(m.weaks.peek(),Some(&(0,None)));
+	}
+
+	#[test]
+	fn expands_forms_with_defmacro() {
+		use crate::macros::Macro;
+		use crate::sym;
+
+		//(defmacro unless (c b) `(if ,c #f ,b))
+		let unless = [
+			Token(sym::DEFMACRO),Token(20),Token(21),Token(22),Cons(2),
+				Token(sym::QUASIQUOTE),
+					Token(23),Token(sym::UNQUOTE),Token(21),Cons(2),Bool(false),Token(sym::UNQUOTE),Token(22),Cons(2),
+				Cons(8),Cons(10),
+			Cons(16),
+		];
+		let macros = [Macro::defmacro(&unless).unwrap()];
+		let code = [
+			Op::Push(Token(20)),Op::Push(Int(1)),Op::Push(Int(2)),Op::List(3),Op::Expand,Op::Halt,
+			Op::Push(Token(20)),Op::Push(Int(1)),Op::List(2),Op::Expand,
+		];
+
+		let mut stack = ArrayStack::<_,32>::new();
+		let mut names = make_storage::<_,8>();
+		let mut m = Machine::new(
+			&code,
+			0,
+			&mut stack,
+			StackRef::from_slice(&mut []),
+			StackRef::from_slice(&mut []),
+			SymbolTable::new(&mut names).unwrap(),
+		);
+		//nothing to expand with
+		assert_eq!(m.run(10),State::Halted);
+		assert_eq!(m.value(),Some(&[Token(20),Int(1),Int(2),Cons(3)][..]));
+
+		let mut m = m.with_macros(Expander{macros:&macros,max_depth:8});
+		m.reset();
+		assert_eq!(m.run(10),State::Halted);
+		assert_eq!(m.value(),Some(&[Token(23),Int(1),Bool(false),Int(2),Cons(4)][..]));
+
+		//a failed expansion keeps the form for a retry
+		m.reset();
+		m.ip = 6;
+		assert_eq!(m.run(10),State::Errored(Error::ArityError));
+		assert_eq!(m.stack.peek_many(3),Some(&[Token(20),Int(1),Cons(2)][..]));
+		assert_eq!(m.stack.write_index(),3);
+	}
+
+	#[test]
+	fn quasiquote_reads_bindings() {
+		use crate::sym;
+
+		//(define x 5) `(a ,x ,@()) with a undefined
+		let code = [
+			Op::Push(Int(5)),Op::Define(10),
+			Op::Push(Token(sym::QUASIQUOTE)),
+				Op::Push(Token(11)),
+				Op::Push(Token(sym::UNQUOTE)),Op::Push(Token(10)),Op::List(2),
+				Op::Push(Token(sym::UNQUOTE_SPLICING)),Op::Push(Nil),Op::List(2),
+				Op::List(3),
+			Op::List(2),
+			Op::Quasiquote,
+			Op::Halt,
+		];
+		let mut stack = ArrayStack::<_,32>::new();
+		let mut env = ArrayStack::<_,1>::new();
+		let mut names = make_storage::<_,8>();
+		let mut m = Machine::new(
+			&code,
+			0,
+			&mut stack,
+			StackRef::from_slice(&mut []),
+			&mut env,
+			SymbolTable::new(&mut names).unwrap(),
+		);
+		assert_eq!(m.run(20),State::Halted);
+		assert_eq!(m.value(),Some(&[Token(11),Int(5),Cons(2)][..]));
+		assert_eq!(m.stack.write_index(),4);
+	}
+}
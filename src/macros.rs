@@ -0,0 +1,660 @@
+use core::mem;
+use crate::quasi;
+use crate::sym;
+use crate::value::{dup_range, list_items, nth_object, objects, slide_down, split_first, Error, ValueStack, ValueTag};
+
+/*
+ * macros are expanded on the form sitting on top of the stack
+ *
+ * syntax-rules never records its bindings,
+ * when a template needs a pattern variable we walk the pattern and the form again
+ * following the ellipsis indices we are currently under.
+ * that keeps the matcher free of any scratch storage
+ * at the cost of rescanning the (usually tiny) pattern.
+ *
+ * the expansion is written into the free space above the form
+ * and then slid down over it.
+ *
+ * defmacro is not hygienic and has no evaluator to run its body in,
+ * the body is a quasiquote whose unquotes name parameters,
+ * a single parameter or a quoted datum.
+ */
+
+/// how deep ellipses may nest inside a single pattern
+pub const MAX_ELLIPSIS: usize = 8;
+
+pub enum Macro<'r> {
+	/// syntax-rules, `rules` is a list of (pattern template) lists
+	Rules{literals:&'r [u16],rules:&'r [ValueTag]},
+	/// defmacro, `params` holds the parameter tokens, see Macro::defmacro for what the body may be
+	Template{params:&'r [ValueTag],body:&'r [ValueTag]},
+	/// rewrites the form on top of the stack into its expansion
+	Native(fn(&mut ValueStack) -> Result<(),Error>),
+}
+
+impl<'r> Macro<'r> {
+	/// reads a (defmacro name (param ...) body) form
+	///
+	/// the body is not run through the evaluator, it has to be one of
+	/// - a quasiquote whose unquotes and splices each name a parameter
+	/// - a bare parameter
+	/// - a quoted datum or a self evaluating atom
+	///
+	/// anything computed such as `,(car x)` fails with SyntaxError when the macro is used
+	pub fn defmacro(form:&'r [ValueTag]) -> Result<(u16,Macro<'r>),Error>{
+		let items = list_items(form).ok_or(Error::SyntaxError)?;
+		if objects(items).count() != 4 {
+			return Err(Error::SyntaxError)
+		}
+		let part = |k| nth_object(items,k,4).1;
+
+		let ([ValueTag::Token(sym::DEFMACRO)],[ValueTag::Token(name)]) = (part(0),part(1)) else {
+			return Err(Error::SyntaxError)
+		};
+		let params = list_items(part(2)).ok_or(Error::SyntaxError)?;
+		if !params.iter().all(|p| matches!(p,ValueTag::Token(_))) {
+			return Err(Error::SyntaxError)
+		}
+		Ok((*name,Macro::Template{params,body:part(3)}))
+	}
+}
+
+pub struct Expander<'r> {
+	pub macros: &'r [(u16,Macro<'r>)],
+	/// bound on both form nesting and how often one form may be rewritten
+	pub max_depth: usize,
+}
+
+fn is_var(literals:&[u16],t:u16) -> bool {
+	t != sym::ELLIPSIS && t != sym::UNDERSCORE && !literals.contains(&t)
+}
+
+fn mentions(pat:&[ValueTag],var:u16) -> bool {
+	pat.contains(&ValueTag::Token(var))
+}
+
+/// elements in a pattern sequence and where its ellipsis is
+/// `tail` counts the elements above the ellipsis
+struct Shape {
+	len: usize,
+	tail: Option<usize>,
+}
+
+impl Shape {
+	fn of(seq:&[ValueTag]) -> Result<Shape,Error>{
+		let mut len = 0;
+		let mut tail = None;
+		for obj in objects(seq) {
+			if obj == [ValueTag::Token(sym::ELLIPSIS)] {
+				if tail.is_some() {
+					return Err(Error::SyntaxError)
+				}
+				tail = Some(len);
+			}
+			len += 1;
+		}
+
+		if tail.is_some_and(|t| t+1 == len) {
+			return Err(Error::SyntaxError)
+		}
+		Ok(Shape{len,tail})
+	}
+
+	/// how many form elements the repeated pattern takes
+	fn reps(&self,form:&[ValueTag]) -> Option<usize> {
+		self.tail?;
+		objects(form).count().checked_sub(self.len-2)
+	}
+}
+
+enum Found<'f> {
+	One(&'f [ValueTag]),
+	/// the variable sits under one more ellipsis, this many times
+	Reps(usize),
+}
+
+fn find_seq<'f>(literals:&[u16],pat:&[ValueTag],form:&'f [ValueTag],var:u16,path:&[usize]) -> Option<Found<'f>> {
+	let shape = Shape::of(pat).ok()?;
+	let reps = shape.reps(form).unwrap_or(0);
+
+	let mut f = objects(form);
+	let mut p = objects(pat).enumerate();
+	while let Some((i,pe)) = p.next() {
+		if Some(i) != shape.tail {
+			let fe = f.next()?;
+			if mentions(pe,var) {
+				return find(literals,pe,fe,var,path)
+			}
+			continue;
+		}
+
+		let (_,rep) = p.next()?;
+		if !mentions(rep,var) {
+			for _ in 0..reps {
+				f.next();
+			}
+			continue;
+		}
+
+		//reps are met from the last one down
+		return match path.split_first() {
+			None => Some(Found::Reps(reps)),
+			Some((&k,rest)) if k < reps => {
+				let fe = f.nth(reps-1-k)?;
+				find(literals,rep,fe,var,rest)
+			}
+			Some(_) => None,
+		}
+	}
+
+	None
+}
+
+fn find<'f>(literals:&[u16],pat:&[ValueTag],form:&'f [ValueTag],var:u16,path:&[usize]) -> Option<Found<'f>> {
+	match pat.last()? {
+		ValueTag::Token(t) if *t == var => Some(Found::One(form)),
+		ValueTag::Cons(_) => find_seq(literals,list_items(pat)?,list_items(form)?,var,path),
+		_ => None,
+	}
+}
+
+fn match_seq(literals:&[u16],pat:&[ValueTag],form:&[ValueTag]) -> Result<bool,Error>{
+	let shape = Shape::of(pat)?;
+	let reps = match (shape.tail,shape.reps(form)) {
+		(Some(_),None) => return Ok(false),
+		(_,reps) => reps.unwrap_or(0),
+	};
+
+	let mut f = objects(form);
+	let mut p = objects(pat).enumerate();
+	while let Some((i,pe)) = p.next() {
+		if Some(i) == shape.tail {
+			let (_,rep) = p.next().ok_or(Error::SyntaxError)?;
+			for _ in 0..reps {
+				let fe = f.next().ok_or(Error::SyntaxError)?;
+				if !match_pat(literals,rep,fe)? {
+					return Ok(false)
+				}
+			}
+			continue;
+		}
+
+		match f.next() {
+			Some(fe) if match_pat(literals,pe,fe)? => {},
+			_ => return Ok(false),
+		}
+	}
+
+	Ok(f.next().is_none())
+}
+
+fn match_pat(literals:&[u16],pat:&[ValueTag],form:&[ValueTag]) -> Result<bool,Error>{
+	match pat.last() {
+		Some(ValueTag::Token(t)) if is_var(literals,*t) || *t == sym::UNDERSCORE => Ok(true),
+		Some(ValueTag::Cons(_)) => {
+			let pat = list_items(pat).ok_or(Error::SyntaxError)?;
+			match list_items(form) {
+				Some(form) => match_seq(literals,pat,form),
+				None => Ok(false),
+			}
+		}
+		_ => Ok(pat == form),
+	}
+}
+
+struct Path {
+	idx: [usize;MAX_ELLIPSIS],
+	len: usize,
+}
+
+impl Path {
+	fn push(&mut self,i:usize) -> Result<(),Error>{
+		*self.idx.get_mut(self.len).ok_or(Error::DepthLimit)? = i;
+		self.len += 1;
+		Ok(())
+	}
+
+	fn pop(&mut self){
+		self.len -= 1;
+	}
+
+	fn as_slice(&self) -> &[usize] {
+		&self.idx[..self.len]
+	}
+}
+
+/// a matched rule, patterns and forms have their keyword dropped
+struct Matched<'a> {
+	literals: &'a [u16],
+	pat: &'a [ValueTag],
+	form: &'a [ValueTag],
+}
+
+impl Matched<'_> {
+	fn lookup(&self,var:u16,path:&Path) -> Option<Found<'_>> {
+		find_seq(self.literals,self.pat,self.form,var,path.as_slice())
+	}
+
+	fn reps(&self,elem:&[ValueTag],path:&Path) -> Result<usize,Error>{
+		let mut reps = None;
+		for slot in elem {
+			if let ValueTag::Token(t) = *slot
+				&& is_var(self.literals,t)
+				&& let Some(Found::Reps(n)) = self.lookup(t,path) {
+				if reps.is_some_and(|r| r != n) {
+					return Err(Error::SyntaxError)
+				}
+				reps = Some(n);
+			}
+		}
+		reps.ok_or(Error::SyntaxError)
+	}
+
+	fn emit(&self,tmpl:&[ValueTag],path:&mut Path,out:&mut ValueStack) -> Result<(),Error>{
+		match tmpl.last() {
+			Some(ValueTag::Token(t)) if is_var(self.literals,*t) && mentions(self.pat,*t) => {
+				match self.lookup(*t,path) {
+					Some(Found::One(obj)) => out.push_slice(obj).map_err(|_| Error::StackOverflow),
+					_ => Err(Error::SyntaxError),
+				}
+			}
+			Some(ValueTag::Cons(_)) => {
+				let items = list_items(tmpl).ok_or(Error::SyntaxError)?;
+				let count = objects(items).count();
+				let start = out.write_index();
+
+				let mut k = 0;
+				while k < count {
+					let (_,elem) = nth_object(items,k,count);
+					let repeated = k+1 < count
+						&& nth_object(items,k+1,count).1 == [ValueTag::Token(sym::ELLIPSIS)];
+
+					if repeated {
+						for i in 0..self.reps(elem,path)? {
+							path.push(i)?;
+							self.emit(elem,path,out)?;
+							path.pop();
+						}
+						k += 2;
+					} else {
+						self.emit(elem,path,out)?;
+						k += 1;
+					}
+				}
+
+				let slots = out.write_index()-start;
+				out.push(ValueTag::Cons(slots)).map_err(|_| Error::StackOverflow)
+			}
+			Some(_) => out.push_slice(tmpl).map_err(|_| Error::StackOverflow),
+			None => Err(Error::SyntaxError),
+		}
+	}
+}
+
+/// the token a list form on top of the stack starts with
+fn head(stack:&ValueStack) -> Option<u16> {
+	let size = stack.peek()?.get_size();
+	let items = list_items(stack.peek_many(size)?)?;
+	match split_first(items)?.0 {
+		[ValueTag::Token(t)] => Some(*t),
+		_ => None,
+	}
+}
+
+/// rewrites the form on top of the stack with the first rule that matches it
+pub fn apply_rules(stack:&mut ValueStack,literals:&[u16],rules:&[ValueTag]) -> Result<(),Error>{
+	let size = stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+	let top = stack.write_index();
+	let start = top.checked_sub(size).ok_or(Error::TypeError)?;
+	let rules = list_items(rules).ok_or(Error::SyntaxError)?;
+	let count = objects(rules).count();
+
+	let (room,mut out) = stack.split();
+	let form = list_items(&room[start..top]).ok_or(Error::SyntaxError)?;
+	let (_,form) = split_first(form).ok_or(Error::SyntaxError)?;
+
+	for k in 0..count {
+		let rule = list_items(nth_object(rules,k,count).1).ok_or(Error::SyntaxError)?;
+		let mut parts = objects(rule);
+		let (Some(tmpl),Some(pat),None) = (parts.next(),parts.next(),parts.next()) else {
+			return Err(Error::SyntaxError)
+		};
+
+		let pat = list_items(pat).ok_or(Error::SyntaxError)?;
+		let (_,pat) = split_first(pat).ok_or(Error::SyntaxError)?;
+		if !match_seq(literals,pat,form)? {
+			continue;
+		}
+
+		let m = Matched{literals,pat,form};
+		let mut path = Path{idx:[0;MAX_ELLIPSIS],len:0};
+		m.emit(tmpl,&mut path,&mut out)?;
+
+		let written = out.write_index();
+		mem::forget(out);
+		unsafe { stack.advance(written) }
+		slide_down(stack,start,written);
+		return Ok(())
+	}
+
+	Err(Error::SyntaxError)
+}
+
+/// copies the argument of the form at [start,top) bound to param t
+fn push_arg(stack:&mut ValueStack,params:&[ValueTag],start:usize,top:usize,t:u16) -> Result<(),Error>{
+	let k = params.iter().position(|p| *p == ValueTag::Token(t)).ok_or(Error::SyntaxError)?;
+	let (room,_) = stack.split();
+	let form = list_items(&room[start..top]).ok_or(Error::SyntaxError)?;
+	let (name,args) = split_first(form).ok_or(Error::SyntaxError)?;
+	let (at,arg) = nth_object(args,k,params.len());
+	let at = start+name.len()+at;
+	let len = arg.len();
+	dup_range(stack,at,len)
+}
+
+/// pushes the value of a defmacro body for the form at [start,top)
+fn eval_body(stack:&mut ValueStack,params:&[ValueTag],body:&[ValueTag],start:usize,top:usize) -> Result<(),Error>{
+	match (body,list_items(body).and_then(split_first)) {
+		([ValueTag::Token(t)],_) if params.contains(&ValueTag::Token(*t)) => push_arg(stack,params,start,top,*t),
+		(_,Some(([ValueTag::Token(sym::QUASIQUOTE)],_))) => {
+			stack.push_slice(body).map_err(|_| Error::StackOverflow)?;
+			quasi::quasiquote(stack,&mut |stack:&mut ValueStack| match stack.peek() {
+				Some(ValueTag::Token(t)) => {
+					let t = *t;
+					stack.pop();
+					push_arg(stack,params,start,top,t)
+				}
+				_ => Err(Error::SyntaxError),
+			})
+		}
+		(_,Some(([ValueTag::Token(sym::QUOTE)],datum))) if objects(datum).count() == 1 => {
+			stack.push_slice(datum).map_err(|_| Error::StackOverflow)
+		}
+		([v],_) if !matches!(v,ValueTag::Token(_)) => stack.push(*v).map_err(|_| Error::StackOverflow),
+		_ => Err(Error::SyntaxError),
+	}
+}
+
+/// rewrites the form on top of the stack by evaluating a defmacro body
+/// with its parameters bound to the arguments of the form
+pub fn apply_template(stack:&mut ValueStack,params:&[ValueTag],body:&[ValueTag]) -> Result<(),Error>{
+	let size = stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+	let top = stack.write_index();
+	let start = top.checked_sub(size).ok_or(Error::TypeError)?;
+	let form = list_items(stack.peek_many(size).ok_or(Error::TypeError)?).ok_or(Error::SyntaxError)?;
+	let (_,args) = split_first(form).ok_or(Error::SyntaxError)?;
+	if objects(args).count() != params.len() {
+		return Err(Error::ArityError)
+	}
+
+	if let Err(e) = eval_body(stack,params,body,start,top) {
+		//nothing of a half built expansion stays
+		stack.flush(stack.write_index()-top);
+		return Err(e)
+	}
+	slide_down(stack,start,stack.write_index()-top);
+	Ok(())
+}
+
+impl<'r> Expander<'r> {
+	fn lookup(&self,t:u16) -> Option<&Macro<'r>> {
+		self.macros.iter().find(|(name,_)| *name == t).map(|(_,m)| m)
+	}
+
+	/// expands every macro use in the form on top of the stack
+	/// quoted and quasiquoted forms are data and are left alone
+	pub fn expand(&self,stack:&mut ValueStack) -> Result<(),Error>{
+		self.expand_at(stack,0)
+	}
+
+	fn expand_at(&self,stack:&mut ValueStack,mut depth:usize) -> Result<(),Error>{
+		loop {
+			if depth > self.max_depth {
+				return Err(Error::DepthLimit)
+			}
+
+			match head(stack) {
+				Some(sym::QUOTE | sym::QUASIQUOTE) => return Ok(()),
+				Some(t) => match self.lookup(t) {
+					Some(Macro::Rules{literals,rules}) => apply_rules(stack,literals,rules)?,
+					Some(Macro::Template{params,body}) => apply_template(stack,params,body)?,
+					Some(Macro::Native(f)) => f(stack)?,
+					None => break,
+				},
+				None => break,
+			}
+			depth += 1;
+		}
+
+		let n = match stack.peek() {
+			Some(ValueTag::Cons(n)) => *n,
+			_ => return Ok(()),
+		};
+		let top = stack.write_index();
+		let start = top.checked_sub(n+1).ok_or(Error::TypeError)?;
+		let count = objects(stack.peek_many(n+1).ok_or(Error::TypeError)?.split_last().unwrap().1).count();
+
+		//expand copies of the elements above the list then slide them over it
+		for k in 0..count {
+			let (room,_) = stack.split();
+			let (at,elem) = nth_object(&room[start..start+n],k,count);
+			let len = elem.len();
+			dup_range(stack,start+at,len)?;
+			self.expand_at(stack,depth+1)?;
+		}
+
+		let written = stack.write_index()-top;
+		slide_down(stack,start,written);
+		stack.push(ValueTag::Cons(written)).map_err(|_| Error::StackOverflow)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ValueTag::*;
+	use crate::array_stack::ArrayStack;
+
+	const U: ValueTag = Token(sym::UNDERSCORE);
+	const ELL: ValueTag = Token(sym::ELLIPSIS);
+	const QUOTE: ValueTag = Token(sym::QUOTE);
+
+	const OR: u16 = 10;
+	const IF: u16 = 11;
+	const E: u16 = 12;
+	const R: u16 = 13;
+	const LET: u16 = 14;
+	const LAMBDA: u16 = 15;
+	const N: u16 = 16;
+	const V: u16 = 17;
+	const B: u16 = 18;
+	const LOOP: u16 = 19;
+	const ARROW: u16 = 20;
+	const UNLESS: u16 = 21;
+
+	//((_) #f)
+	//((_ e) e)
+	//((_ e r ...) (if e e (my-or r ...)))
+	const OR_RULES: [ValueTag;24] = [
+		U,Cons(1),Bool(false),Cons(3),
+		U,Token(E),Cons(2),Token(E),Cons(4),
+		U,Token(E),Token(R),ELL,Cons(4),
+			Token(IF),Token(E),Token(E),Token(OR),Token(R),ELL,Cons(3),Cons(7),
+		Cons(13),
+		Cons(23),
+	];
+
+	fn expander<'r>(macros:&'r [(u16,Macro<'r>)]) -> Expander<'r> {
+		Expander{macros,max_depth:16}
+	}
+
+	#[test]
+	fn recursive_or() {
+		let macros = [(OR,Macro::Rules{literals:&[],rules:&OR_RULES})];
+		let mut stack = ArrayStack::<_,64>::new();
+
+		stack.push_slice(&[Token(OR),Int(1),Int(2),Int(3),Cons(4)]).unwrap();
+		expander(&macros).expand(&mut stack.view()).unwrap();
+
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[
+			Token(IF),Int(1),Int(1),
+				Token(IF),Int(2),Int(2),Int(3),Cons(4),
+			Cons(8),
+		]);
+
+		stack.flush_all();
+		stack.push_slice(&[Token(OR),Cons(1)]).unwrap();
+		expander(&macros).expand(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[Bool(false)]);
+	}
+
+	#[test]
+	fn nested_ellipsis() {
+		let rules = [
+			U,Token(N),Token(V),Cons(2),ELL,Cons(4),Token(B),Cons(7),
+				Token(LAMBDA),Token(N),ELL,Cons(2),Token(B),Cons(5),Token(V),ELL,Cons(8),
+			Cons(17),
+			Cons(18),
+		];
+		let macros = [(LET,Macro::Rules{literals:&[],rules:&rules})];
+		let mut stack = ArrayStack::<_,64>::new();
+
+		//(my-let ((x 1) (y (q 2))) body) with x=E y=R q=IF
+		stack.push_slice(&[
+			Token(LET),
+				Token(E),Int(1),Cons(2),
+				Token(R),Token(IF),Int(2),Cons(2),Cons(4),
+			Cons(8),
+			Token(B),
+			Cons(11),
+		]).unwrap();
+		expander(&macros).expand(&mut stack.view()).unwrap();
+
+		//((lambda (x y) body) 1 (q 2))
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[
+			Token(LAMBDA),Token(E),Token(R),Cons(2),Token(B),Cons(5),
+			Int(1),
+			Token(IF),Int(2),Cons(2),
+			Cons(10),
+		]);
+	}
+
+	#[test]
+	fn literals_pick_the_rule() {
+		//((_ a => b) b) ((_ a b) a)
+		let rules = [
+			U,Token(E),Token(ARROW),Token(R),Cons(4),Token(R),Cons(6),
+			U,Token(E),Token(R),Cons(3),Token(E),Cons(5),
+			Cons(13),
+		];
+		let macros = [(OR,Macro::Rules{literals:&[ARROW],rules:&rules})];
+		let mut stack = ArrayStack::<_,32>::new();
+
+		stack.push_slice(&[Token(OR),Int(1),Token(ARROW),Int(2),Cons(4)]).unwrap();
+		expander(&macros).expand(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(),Some(Int(2)));
+
+		stack.push_slice(&[Token(OR),Int(1),Int(2),Cons(3)]).unwrap();
+		expander(&macros).expand(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(),Some(Int(1)));
+
+		stack.push_slice(&[Token(OR),Int(1),Cons(2)]).unwrap();
+		assert!(matches!(expander(&macros).expand(&mut stack.view()),Err(Error::SyntaxError)));
+	}
+
+	#[test]
+	fn quote_is_left_alone() {
+		let macros = [(OR,Macro::Rules{literals:&[],rules:&OR_RULES})];
+		let mut stack = ArrayStack::<_,32>::new();
+
+		let form = [QUOTE,Token(OR),Int(1),Cons(2),Cons(4)];
+		stack.push_slice(&form).unwrap();
+		expander(&macros).expand(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&form);
+
+		//a quasiquote template is data too
+		stack.flush_all();
+		let form = [Token(sym::QUASIQUOTE),Token(OR),Int(1),Cons(2),Cons(4)];
+		stack.push_slice(&form).unwrap();
+		expander(&macros).expand(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&form);
+	}
+
+	#[test]
+	fn runaway_expansion_is_an_error() {
+		//((_) (loop))
+		let rules = [U,Cons(1),Token(LOOP),Cons(1),Cons(4),Cons(5)];
+		let macros = [(LOOP,Macro::Rules{literals:&[],rules:&rules})];
+		let mut stack = ArrayStack::<_,32>::new();
+
+		stack.push_slice(&[Token(LOOP),Cons(1)]).unwrap();
+		assert!(matches!(expander(&macros).expand(&mut stack.view()),Err(Error::DepthLimit)));
+	}
+
+	#[test]
+	fn native_transformer() {
+		fn answer(stack:&mut ValueStack) -> Result<(),Error>{
+			let size = stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+			stack.flush(size);
+			stack.push(Int(42)).map_err(|_| Error::StackOverflow)
+		}
+
+		let macros = [(LOOP,Macro::Native(answer))];
+		let mut stack = ArrayStack::<_,32>::new();
+
+		stack.push_slice(&[Token(IF),Token(LOOP),Int(1),Cons(2),Cons(4)]).unwrap();
+		expander(&macros).expand(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[Token(IF),Int(42),Cons(2)]);
+	}
+
+	#[test]
+	fn defmacro_with_quasiquote() {
+		const QQ: ValueTag = Token(sym::QUASIQUOTE);
+		const UQ: ValueTag = Token(sym::UNQUOTE);
+		const UQS: ValueTag = Token(sym::UNQUOTE_SPLICING);
+
+		//(defmacro unless (e b) `(if ,e #f ,b))
+		let unless = [
+			Token(sym::DEFMACRO),Token(UNLESS),Token(E),Token(B),Cons(2),
+				QQ,Token(IF),UQ,Token(E),Cons(2),Bool(false),UQ,Token(B),Cons(2),Cons(8),Cons(10),
+			Cons(16),
+		];
+		//(defmacro my-list (e) `(r ,@e))
+		let my_list = [
+			Token(sym::DEFMACRO),Token(LOOP),Token(E),Cons(1),
+				QQ,Token(R),UQS,Token(E),Cons(2),Cons(4),Cons(6),
+			Cons(11),
+		];
+		let macros = [Macro::defmacro(&unless).unwrap(),Macro::defmacro(&my_list).unwrap()];
+		assert_eq!(macros[0].0,UNLESS);
+		let mut stack = ArrayStack::<_,64>::new();
+
+		//(unless (my-list (1 2)) 3) => (if (r 1 2) #f 3)
+		stack.push_slice(&[Token(UNLESS),Token(LOOP),Int(1),Int(2),Cons(2),Cons(4),Int(3),Cons(7)]).unwrap();
+		expander(&macros).expand(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[
+			Token(IF),Token(R),Int(1),Int(2),Cons(3),Bool(false),Int(3),Cons(7),
+		]);
+
+		//arguments are counted and a failed expansion leaves the form alone
+		stack.flush_all();
+		let form = [Token(UNLESS),Int(1),Cons(2)];
+		stack.push_slice(&form).unwrap();
+		assert!(matches!(expander(&macros).expand(&mut stack.view()),Err(Error::ArityError)));
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&form);
+
+		//the body is not evaluated so unquotes can only name parameters
+		let bad = [
+			Token(sym::DEFMACRO),Token(LOOP),Token(E),Cons(1),
+				QQ,UQ,Token(R),Cons(2),Cons(4),
+			Cons(9),
+		];
+		let macros = [Macro::defmacro(&bad).unwrap()];
+		stack.flush_all();
+		stack.push_slice(&[Token(LOOP),Int(1),Cons(2)]).unwrap();
+		assert!(matches!(expander(&macros).expand(&mut stack.view()),Err(Error::SyntaxError)));
+		assert_eq!(stack.len(),3);
+
+		assert!(matches!(Macro::defmacro(&unless[1..]),Err(Error::SyntaxError)));
+	}
+}
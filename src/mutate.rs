@@ -0,0 +1,349 @@
+use crate::gc::WeakSlot;
+use crate::table;
+use crate::value::{objects, split_first, Error, ValueStack, ValueTag};
+use crate::vector;
+
+/*
+ * replacing an object buried in the stack
+ *
+ * values are inline so a new value of another size moves everything above
+ * the old one and changes the header of every object around it.
+ * the new value comes from the top of the stack which is exactly the room
+ * a larger value needs, so nothing here needs free slots.
+ *
+ * indices into the stack held elsewhere are fixed up too: bindings, weak slots
+ * and the own slot of escape continuations. a binding into the old value is pointed
+ * at the new one, the consumed new value must not be bound (see Op::Define).
+ * a weak slot into the old value is cleared, what it referred to is gone.
+ *
+ * a replacement is checked completely before anything moves.
+ */
+
+/// what a replacement will do, found without changing anything
+struct Plan {
+	/// first slot of the old object
+	start: usize,
+	old: usize,
+	new: usize,
+	/// some object around it is a table whose index has to be rebuilt
+	tables: bool,
+}
+
+/// walks from the objects below hi down to the one whose header is at `at`
+/// calling visit with the header of every object around it, outermost first,
+/// and the position of the child holding it counted from the top of the payload
+fn descend(room:&mut [ValueTag],hi:usize,at:usize,mut visit:impl FnMut(&mut [ValueTag],usize,usize) -> Result<(),Error>) -> Result<(usize,usize),Error>{
+	if at >= hi {
+		return Err(Error::OutOfRange)
+	}
+
+	let (mut lo,mut hi) = (0,hi);
+	let mut parent = None;
+	loop {
+		let mut end = hi;
+		let mut found = None;
+		for (pos,obj) in objects(&room[lo..hi]).enumerate() {
+			let start = end-obj.len();
+			if start <= at {
+				found = Some((pos,start));
+				break
+			}
+			end = start;
+		}
+
+		let (pos,start) = found.ok_or(Error::OutOfRange)?;
+		if let Some(h) = parent {
+			visit(room,h,pos)?;
+		}
+		if end-1 == at {
+			return Ok((start,end-start))
+		}
+		parent = Some(end-1);
+		lo = start;
+		hi = end-1;
+	}
+}
+
+/// [entries | index | Int(cap) Int(count) Table] from the top: the count, the cap,
+/// cap index slots, then value and key of each entry
+/// only entries may change and keys have to stay single slots
+fn table_child(room:&[ValueTag],h:usize,pos:usize,new:usize) -> Result<(),Error>{
+	let cap = match h.checked_sub(2).map(|i| room[i]) {
+		Some(ValueTag::Int(c)) => usize::try_from(c).map_err(|_| Error::TypeError)?,
+		_ => return Err(Error::TypeError),
+	};
+	match pos.checked_sub(cap+2) {
+		Some(i) if i%2 == 0 || new == 1 => Ok(()),
+		_ => Err(Error::TypeError),
+	}
+}
+
+fn plan(room:&mut [ValueTag],hi:usize,at:usize,new:usize) -> Result<Plan,Error>{
+	let mut tables = false;
+	let (start,old) = descend(room,hi,at,|room,h,pos| match room[h] {
+		ValueTag::Cons(_) | ValueTag::Func(_) => Ok(()),
+		//the element count sits right under the header
+		ValueTag::Vector(_) if pos > 0 => Ok(()),
+		ValueTag::Table(_) => {
+			tables = true;
+			table_child(room,h,pos,new)
+		}
+		_ => Err(Error::TypeError),
+	})?;
+	Ok(Plan{start,old,new,tables})
+}
+
+fn resized(tag:ValueTag,delta:isize) -> ValueTag {
+	match tag {
+		ValueTag::Cons(u) => ValueTag::Cons(u.wrapping_add_signed(delta)),
+		ValueTag::Func(u) => ValueTag::Func(u.wrapping_add_signed(delta)),
+		ValueTag::Vector(u) => ValueTag::Vector(u.wrapping_add_signed(delta)),
+		ValueTag::Table(u) => ValueTag::Table(u.wrapping_add_signed(delta)),
+		other => other,
+	}
+}
+
+/// carries out a plan made against the stack as it is, the new object being on top
+fn apply(stack:&mut ValueStack,bindings:&mut [(u16,usize)],weaks:&mut [WeakSlot],p:&Plan){
+	let top = stack.write_index();
+	let end = p.start+p.old;
+	let delta = p.new as isize - p.old as isize;
+
+	let (room,_) = stack.split();
+	if delta == 0 {
+		room.copy_within(top-p.new..top, p.start);
+	} else {
+		//headers first while the old sizes still describe the layout
+		let _ = descend(room,top-p.new,end-1,|room,h,_| {
+			room[h] = resized(room[h],delta);
+			Ok(())
+		});
+
+		//[old rest new] -> [new old rest] -> [new rest]
+		let span = &mut room[p.start..top];
+		span.rotate_right(p.new);
+		let rest = top-p.new-end;
+		span.copy_within(p.new+p.old..p.new+p.old+rest, p.new);
+	}
+	stack.flush(p.old);
+
+	let (room,_) = stack.split();
+	for (_,target) in weaks.iter_mut() {
+		if target.is_some_and(|at| (p.start..end).contains(&at)) {
+			*target = None;
+		}
+	}
+	if delta != 0 {
+		let moved = |i:usize| match i {
+			i if i >= end => i.wrapping_add_signed(delta),
+			i if i >= p.start => p.start+p.new-1,
+			i => i,
+		};
+		for (_,at) in bindings.iter_mut() {
+			*at = moved(*at);
+		}
+		for (_,target) in weaks.iter_mut() {
+			if let Some(at) = target {
+				*at = moved(*at);
+			}
+		}
+		for v in room.iter_mut() {
+			if let ValueTag::Escape(w,..) = v && *w as usize >= end {
+				*w = moved(*w as usize) as u32;
+			}
+		}
+	}
+
+	if p.tables {
+		let hi = room.len();
+		let _ = descend(room,hi,p.start+p.new-1,|room,h,_| {
+			if let ValueTag::Table(_) = room[h] {
+				table::reindex(room,h+1)?;
+			}
+			Ok(())
+		});
+	}
+}
+
+/// slots taken by the object on top, checking it is all there
+fn top_size(stack:&ValueStack) -> Result<usize,Error>{
+	let new = stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+	if new > stack.write_index() {
+		return Err(Error::StackUnderflow)
+	}
+	Ok(new)
+}
+
+/// replaces the object whose header is at `at` with the object on top,
+/// anywhere below it and at any depth inside other objects
+/// stack: [.. old .. new] -> [.. new ..]
+pub fn replace(stack:&mut ValueStack,bindings:&mut [(u16,usize)],weaks:&mut [WeakSlot],at:usize) -> Result<(),Error>{
+	let new = top_size(stack)?;
+	let hi = stack.write_index()-new;
+	let (room,_) = stack.split();
+	let p = plan(room,hi,at,new)?;
+	apply(stack,bindings,weaks,&p);
+	Ok(())
+}
+
+/// the header of the first element of the list whose header is at `at`
+pub fn car_of(room:&[ValueTag],at:usize) -> Result<usize,Error>{
+	let n = match room.get(at) {
+		Some(ValueTag::Cons(n)) if *n > 0 => *n,
+		Some(_) => return Err(Error::TypeError),
+		None => return Err(Error::OutOfRange),
+	};
+	let start = at.checked_sub(n).ok_or(Error::TypeError)?;
+	let (first,_) = split_first(&room[start..at]).ok_or(Error::TypeError)?;
+	Ok(start+first.len()-1)
+}
+
+/// sets element i of the vector whose header is at `at`
+/// stack: [.. Int(i) x] -> [..]
+pub fn vector_set_at(stack:&mut ValueStack,bindings:&mut [(u16,usize)],weaks:&mut [WeakSlot],at:usize) -> Result<(),Error>{
+	let new = top_size(stack)?;
+	let index_at = stack.write_index().checked_sub(new+1).ok_or(Error::StackUnderflow)?;
+	let (room,_) = stack.split();
+	let i = match room[index_at] {
+		ValueTag::Int(i) => usize::try_from(i).map_err(|_| Error::OutOfRange)?,
+		_ => return Err(Error::TypeError),
+	};
+
+	if at >= index_at {
+		return Err(Error::OutOfRange)
+	}
+	let (start,len) = vector::element_of(room,at+1,i)?;
+	let p = plan(room,index_at,start+len-1,new)?;
+
+	//the index goes first, nothing below it moves
+	let _ = stack.drop_inside(new,1);
+	apply(stack,bindings,weaks,&p);
+	Ok(())
+}
+
+/// (set-car! l x)
+/// stack: [list x] -> [list]
+pub fn set_car(stack:&mut ValueStack) -> Result<(),Error>{
+	let new = top_size(stack)?;
+	let at = stack.write_index().checked_sub(new+1).ok_or(Error::StackUnderflow)?;
+	let (room,_) = stack.split();
+	let car = car_of(room,at)?;
+	replace(stack,&mut [],&mut [],car)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ValueTag::*;
+	use crate::array_stack::ArrayStack;
+
+	#[test]
+	fn same_size_is_a_copy() {
+		let mut stack = ArrayStack::<_,8>::new();
+		stack.push_slice(&[Int(1),Int(2),Cons(2),Nil,Char('x')]).unwrap();
+		let mut bindings = [(5,2),(6,3)];
+
+		replace(&mut stack.view(),&mut bindings,&mut [],1).unwrap();
+		assert_eq!(stack.peek_many(4),Some(&[Int(1),Char('x'),Cons(2),Nil][..]));
+		assert_eq!(bindings,[(5,2),(6,3)]);
+	}
+
+	#[test]
+	fn growing_fixes_headers_and_references() {
+		let mut stack = ArrayStack::<_,16>::new();
+		//((1 2) 3) then a bound nil, then the new value (7 8)
+		stack.push_slice(&[Int(1),Int(2),Cons(2),Int(3),Cons(4),Escape(6,0,0,0),Nil]).unwrap();
+		stack.push_slice(&[Int(7),Int(8),Cons(2)]).unwrap();
+		let mut bindings = [(5,4),(6,6),(7,0)];
+
+		//replace the 2
+		replace(&mut stack.view(),&mut bindings,&mut [],1).unwrap();
+		assert_eq!(stack.peek_many(9),Some(&[
+			Int(1),Int(7),Int(8),Cons(2),Cons(4),Int(3),Cons(6),Escape(8,0,0,0),Nil
+		][..]));
+		assert_eq!(stack.len(),9);
+		assert_eq!(bindings,[(5,6),(6,8),(7,0)]);
+
+		//and shrink it back, a binding into it moves to the new value
+		stack.push(Int(2)).unwrap();
+		let mut inner = [(9,2)];
+		replace(&mut stack.view(),&mut inner,&mut [],3).unwrap();
+		assert_eq!(stack.peek_many(7),Some(&[Int(1),Int(2),Cons(2),Int(3),Cons(4),Escape(6,0,0,0),Nil][..]));
+		assert_eq!(inner,[(9,1)]);
+	}
+
+	#[test]
+	fn bad_targets_change_nothing() {
+		let mut stack = ArrayStack::<_,16>::new();
+		stack.push_slice(&[Int(5),Int(6),Int(2),Vector(3),Nil,Nil,Cons(2)]).unwrap();
+		let before = [Int(5),Int(6),Int(2),Vector(3),Nil,Nil,Cons(2)];
+
+		//the element count of a vector
+		assert_eq!(replace(&mut stack.view(),&mut [],&mut [],2),Err(Error::TypeError));
+		//inside the new value itself
+		assert_eq!(replace(&mut stack.view(),&mut [],&mut [],5),Err(Error::OutOfRange));
+		assert_eq!(stack.peek_many(7),Some(&before[..]));
+
+		//a vector element is fine
+		replace(&mut stack.view(),&mut [],&mut [],0).unwrap();
+		assert_eq!(stack.peek_many(6),Some(&[Nil,Nil,Cons(2),Int(6),Int(2),Vector(5)][..]));
+		assert_eq!(stack.len(),6);
+	}
+
+	#[test]
+	fn tables_are_reindexed() {
+		use crate::table::{make_table, table_ref, table_set};
+
+		let mut stack = ArrayStack::<_,32>::new();
+		stack.push(Int(4)).unwrap();
+		make_table(&mut stack.view()).unwrap();
+		stack.push_slice(&[Int(1),Int(10),Int(11),Cons(2)]).unwrap();
+		table_set(&mut stack.view()).unwrap();
+		stack.push_slice(&[Int(2),Int(20)]).unwrap();
+		table_set(&mut stack.view()).unwrap();
+
+		//keys must stay single slots
+		stack.push_slice(&[Nil,Cons(1)]).unwrap();
+		assert_eq!(replace(&mut stack.view(),&mut [],&mut [],0),Err(Error::TypeError));
+		stack.flush(2);
+
+		//grow the 10 inside the first value, the second one moves up
+		stack.push_slice(&[Char('a'),Char('b'),Cons(2)]).unwrap();
+		replace(&mut stack.view(),&mut [],&mut [],1).unwrap();
+
+		stack.push_slice(&[Int(2),Nil]).unwrap();
+		table_ref(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(),Some(Int(20)));
+	}
+
+	#[test]
+	fn set_car_of_a_list() {
+		let mut stack = ArrayStack::<_,16>::new();
+		stack.push_slice(&[Int(1),Int(2),Int(3),Cons(2),Cons(4)]).unwrap();
+		stack.push_slice(&[Char('a'),Char('b'),Cons(2)]).unwrap();
+
+		set_car(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(7),Some(&[Char('a'),Char('b'),Cons(2),Int(2),Int(3),Cons(2),Cons(6)][..]));
+
+		stack.push_slice(&[Nil,Cons(0),Nil]).unwrap();
+		assert_eq!(set_car(&mut stack.view()),Err(Error::TypeError));
+	}
+
+	#[test]
+	fn vector_set_in_place() {
+		let mut stack = ArrayStack::<_,16>::new();
+		//a bound vector #(5 6) with something on top of it
+		stack.push_slice(&[Int(5),Int(6),Int(2),Vector(3),Bool(true)]).unwrap();
+		let mut bindings = [(1,3),(2,4)];
+
+		stack.push_slice(&[Int(1),Nil,Nil,Cons(2)]).unwrap();
+		vector_set_at(&mut stack.view(),&mut bindings,&mut [],3).unwrap();
+		assert_eq!(stack.peek_many(7),Some(&[Int(5),Nil,Nil,Cons(2),Int(2),Vector(5),Bool(true)][..]));
+		assert_eq!(stack.len(),7);
+		assert_eq!(bindings,[(1,5),(2,6)]);
+
+		stack.push_slice(&[Int(2),Nil]).unwrap();
+		assert_eq!(vector_set_at(&mut stack.view(),&mut bindings,&mut [],5),Err(Error::OutOfRange));
+		assert_eq!(stack.len(),9);
+	}
+}
@@ -0,0 +1,182 @@
+use crate::stack::StackRef;
+use crate::sym::SymbolTable;
+use crate::value::{objects, Error, ValueStack, ValueTag};
+
+/*
+ * host functions scripts can call by name
+ *
+ * a native is referred to by a Native(id) value holding its place in the table,
+ * the evaluator checks the declared arity before handing over the arguments
+ * so a native only ever sees an argument count it asked for.
+ */
+
+/// pops `argc` arguments and pushes the result
+/// like a primitive it should check everything before consuming anything
+pub type NativeFn = fn(&mut ValueStack,usize) -> Result<(),Error>;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Arity {
+	Exactly(usize),
+	AtLeast(usize),
+}
+
+impl Arity {
+	pub fn accepts(self,argc:usize) -> bool {
+		match self {
+			Arity::Exactly(n) => argc == n,
+			Arity::AtLeast(n) => argc >= n,
+		}
+	}
+}
+
+#[derive(Debug,Clone,Copy)]
+pub struct Native {
+	/// the symbol it was registered under
+	pub name: u16,
+	pub arity: Arity,
+	pub func: NativeFn,
+}
+
+/// a fixed number of natives backed by a buffer
+pub struct NativeTable<'a> {
+	entries: StackRef<'a, Native>,
+}
+
+impl<'a> NativeTable<'a> {
+	pub fn new(mem:impl Into<StackRef<'a, Native>>) -> Self {
+		Self{entries:mem.into()}
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.write_index()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	fn all(&self) -> &[Native] {
+		self.entries.peek_many(self.len()).unwrap_or(&[])
+	}
+
+	pub fn get(&self,id:u16) -> Option<&Native> {
+		self.all().get(id as usize)
+	}
+
+	/// the id of the native registered under a symbol
+	pub fn find(&self,name:u16) -> Option<u16> {
+		self.all().iter().position(|n| n.name == name).map(|i| i as u16)
+	}
+
+	/// registers func under name returning the value scripts use to call it
+	/// registering a name again replaces the older function
+	pub fn register<'s>(&mut self,symbols:&mut SymbolTable<'s>,name:&'s str,arity:Arity,func:NativeFn) -> Result<ValueTag,Error>{
+		let name = symbols.intern(name)?;
+		let native = Native{name,arity,func};
+
+		if let Some(id) = self.find(name) {
+			let (all,_) = self.entries.split();
+			all[id as usize] = native;
+			return Ok(ValueTag::Native(id))
+		}
+
+		let id = u16::try_from(self.len()).map_err(|_| Error::CapacityError)?;
+		self.entries.push(native).map_err(|_| Error::CapacityError)?;
+		Ok(ValueTag::Native(id))
+	}
+
+	/// calls the native on top of the stack with the `argc` objects below it
+	/// on failure the native is left where it was
+	pub fn call(&self,stack:&mut ValueStack,argc:usize) -> Result<(),Error>{
+		let native = match stack.peek() {
+			Some(ValueTag::Native(id)) => *self.get(*id).ok_or(Error::OutOfRange)?,
+			Some(_) => return Err(Error::TypeError),
+			None => return Err(Error::StackUnderflow),
+		};
+		if !native.arity.accepts(argc) {
+			return Err(Error::ArityError)
+		}
+
+		let (room,_) = stack.split();
+		let args = &room[..room.len()-1];
+		if objects(args).take(argc).count() < argc {
+			return Err(Error::StackUnderflow)
+		}
+
+		let f = stack.pop().ok_or(Error::StackUnderflow)?;
+		(native.func)(stack,argc).inspect_err(|_| {
+			//the slot was just freed so this cannot fail
+			let _ = stack.push(f);
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ValueTag::*;
+	use crate::array_stack::ArrayStack;
+	use crate::stack::make_storage;
+
+	fn sum(stack:&mut ValueStack,argc:usize) -> Result<(),Error>{
+		let args = stack.peek_many(argc).ok_or(Error::StackUnderflow)?;
+		let mut total = 0;
+		for a in args {
+			match a {
+				Int(x) => total += x,
+				_ => return Err(Error::TypeError),
+			}
+		}
+		stack.flush(argc);
+		stack.push(Int(total)).map_err(|_| Error::StackOverflow)
+	}
+
+	fn zero(stack:&mut ValueStack,_:usize) -> Result<(),Error>{
+		stack.push(Int(0)).map_err(|_| Error::StackOverflow)
+	}
+
+	#[test]
+	fn register_and_call() {
+		let mut names = make_storage::<_,16>();
+		let mut symbols = SymbolTable::new(&mut names).unwrap();
+		let mut mem = ArrayStack::<_,2>::new();
+		let mut natives = NativeTable::new(&mut mem);
+
+		let f = natives.register(&mut symbols,"sum",Arity::AtLeast(1),sum).unwrap();
+		let z = natives.register(&mut symbols,"zero",Arity::Exactly(0),zero).unwrap();
+		assert_eq!(f,Native(0));
+		assert_eq!(natives.find(symbols.find("zero").unwrap()),Some(1));
+		assert_eq!(natives.register(&mut symbols,"more",Arity::Exactly(0),zero),Err(Error::CapacityError));
+		assert_eq!(natives.register(&mut symbols,"zero",Arity::Exactly(0),zero),Ok(z));
+
+		let mut stack = ArrayStack::<_,8>::new();
+		stack.push_slice(&[Int(1),Int(2),Int(3),f]).unwrap();
+		natives.call(&mut stack.view(),3).unwrap();
+		assert_eq!(stack.peek_many(1),Some(&[Int(6)][..]));
+		assert_eq!(stack.len(),1);
+
+		stack.push(z).unwrap();
+		natives.call(&mut stack.view(),0).unwrap();
+		assert_eq!(stack.peek_many(2),Some(&[Int(6),Int(0)][..]));
+	}
+
+	#[test]
+	fn bad_calls_leave_the_stack() {
+		let mut names = make_storage::<_,8>();
+		let mut symbols = SymbolTable::new(&mut names).unwrap();
+		let mut mem = ArrayStack::<_,1>::new();
+		let mut natives = NativeTable::new(&mut mem);
+		let f = natives.register(&mut symbols,"sum",Arity::AtLeast(1),sum).unwrap();
+
+		let mut stack = ArrayStack::<_,8>::new();
+		stack.push_slice(&[Int(1),Nil,f]).unwrap();
+
+		assert_eq!(natives.call(&mut stack.view(),0),Err(Error::ArityError));
+		assert_eq!(natives.call(&mut stack.view(),3),Err(Error::StackUnderflow));
+		assert_eq!(natives.call(&mut stack.view(),2),Err(Error::TypeError));
+		assert_eq!(stack.peek_many(3),Some(&[Int(1),Nil,f][..]));
+
+		stack.push(Int(1)).unwrap();
+		assert_eq!(natives.call(&mut stack.view(),1),Err(Error::TypeError));
+	}
+}
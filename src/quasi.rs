@@ -0,0 +1,249 @@
+use crate::sym;
+use crate::value::{dup_range, list_items, nth_object, objects, slide_down, split_first, Error, ValueStack, ValueTag};
+
+/*
+ * quasiquote builds its result straight on top of the stack in postfix order
+ *
+ * every element of a template is either copied or evaluated into place,
+ * once all of them are up we push the header counting the slots they took.
+ * splicing is then free: the spliced list is evaluated in place
+ * and we simply pop its header leaving its items among ours
+ * no matter how deep they are nested.
+ */
+
+/// evaluates the form on top of the stack replacing it with its value
+pub type Eval<'e> = dyn FnMut(&mut ValueStack) -> Result<(),Error> + 'e;
+
+/// a (tag x) list with a known tag, returns the tag and where x sits
+fn wrapped(obj:&[ValueTag]) -> Option<(u16,usize,usize)> {
+	let items = list_items(obj)?;
+	let (head,rest) = split_first(items)?;
+	let tag = match head {
+		[ValueTag::Token(t)] if matches!(*t,sym::QUASIQUOTE|sym::UNQUOTE|sym::UNQUOTE_SPLICING) => *t,
+		_ => return None,
+	};
+
+	if objects(rest).count() != 1 {
+		return None
+	}
+	Some((tag,head.len(),rest.len()))
+}
+
+/// evaluates the (quasiquote template) form on top of the stack
+pub fn quasiquote(stack:&mut ValueStack,eval:&mut Eval) -> Result<(),Error>{
+	let size = stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+	let top = stack.write_index();
+	let start = top.checked_sub(size).ok_or(Error::TypeError)?;
+
+	let (at,len) = match wrapped(stack.peek_many(size).ok_or(Error::TypeError)?) {
+		Some((sym::QUASIQUOTE,at,len)) => (at,len),
+		_ => return Err(Error::SyntaxError),
+	};
+
+	build(stack,start+at,len,1,eval)?;
+	slide_down(stack,start,stack.write_index()-top);
+	Ok(())
+}
+
+/// pushes the value of the template at [at,at+len) quoted `level` times
+fn build(stack:&mut ValueStack,at:usize,len:usize,level:usize,eval:&mut Eval) -> Result<(),Error>{
+	let (room,_) = stack.split();
+	let obj = &room[at..at+len];
+
+	match (obj.last(),wrapped(obj)) {
+		(_,Some((sym::UNQUOTE,off,len))) if level == 1 => {
+			dup_range(stack,at+off,len)?;
+			eval(stack)
+		}
+		(_,Some((sym::UNQUOTE_SPLICING,..))) if level == 1 => Err(Error::SyntaxError),
+		(_,Some((tag,off,len))) => {
+			let level = if tag == sym::QUASIQUOTE { level+1 } else { level-1 };
+			let start = stack.write_index();
+			stack.push(ValueTag::Token(tag)).map_err(|_| Error::StackOverflow)?;
+			build(stack,at+off,len,level,eval)?;
+			let slots = stack.write_index()-start;
+			stack.push(ValueTag::Cons(slots)).map_err(|_| Error::StackOverflow)
+		}
+		(Some(ValueTag::Cons(n)),None) => {
+			let n = *n;
+			let slots = build_items(stack,at,n,level,eval)?.0;
+			stack.push(ValueTag::Cons(slots)).map_err(|_| Error::StackOverflow)
+		}
+		(Some(ValueTag::Vector(n)),None) => {
+			//the element count under the header is not part of the template
+			let n = *n;
+			let (slots,count) = build_items(stack,at,n-1,level,eval)?;
+			stack.push_n([ValueTag::Int(count as i64),ValueTag::Vector(slots+1)])
+				.map_err(|_| Error::StackOverflow)
+		}
+		_ => dup_range(stack,at,len),
+	}
+}
+
+/// builds the n slots of items starting at `at`
+/// returns the slots and number of objects pushed
+fn build_items(stack:&mut ValueStack,at:usize,n:usize,level:usize,eval:&mut Eval) -> Result<(usize,usize),Error>{
+	let start = stack.write_index();
+	let count = {
+		let (room,_) = stack.split();
+		objects(&room[at..at+n]).count()
+	};
+
+	let mut pushed = 0;
+	for k in 0..count {
+		let (room,_) = stack.split();
+		let items = &room[at..at+n];
+		let (off,elem) = nth_object(items,k,count);
+		let len = elem.len();
+
+		match wrapped(elem) {
+			Some((sym::UNQUOTE_SPLICING,o,l)) if level == 1 => {
+				dup_range(stack,at+off+o,l)?;
+				eval(stack)?;
+
+				let spliced = match stack.pop() {
+					Some(ValueTag::Cons(m)) => m,
+					//the empty list splices nothing
+					Some(ValueTag::Nil) => 0,
+					_ => return Err(Error::TypeError),
+				};
+				let top = stack.write_index();
+				let (room,_) = stack.split();
+				pushed += objects(&room[top-spliced..top]).count();
+			}
+			_ => {
+				build(stack,at+off,len,level,eval)?;
+				pushed += 1;
+			}
+		}
+	}
+
+	Ok((stack.write_index()-start,pushed))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ValueTag::*;
+	use crate::array_stack::ArrayStack;
+
+	const QQ: ValueTag = Token(sym::QUASIQUOTE);
+	const UQ: ValueTag = Token(sym::UNQUOTE);
+	const UQS: ValueTag = Token(sym::UNQUOTE_SPLICING);
+
+	const A: u16 = 10;
+	const B: u16 = 11;
+	const X: u16 = 12;
+	const L: u16 = 13;
+	const NESTED: u16 = 14;
+	const NUM: u16 = 15;
+	const EMPTY: u16 = 16;
+
+	/// x is 5, l is (1 2 3), nested is ((1 2) #(3)), num is 7 and empty is ()
+	fn eval(stack:&mut ValueStack) -> Result<(),Error>{
+		let value: &[ValueTag] = match stack.pop() {
+			Some(Token(X)) => &[Int(5)],
+			Some(Token(L)) => &[Int(1),Int(2),Int(3),Cons(3)],
+			Some(Token(NESTED)) => &[Int(1),Int(2),Cons(2),Int(3),Int(1),Vector(2),Cons(6)],
+			Some(Token(NUM)) => &[Int(7)],
+			Some(Token(EMPTY)) => &[Nil],
+			_ => return Err(Error::TypeError),
+		};
+		stack.push_slice(value).map_err(|_| Error::StackOverflow)
+	}
+
+	fn run(stack:&mut ValueStack,form:&[ValueTag]) -> Result<(),Error>{
+		stack.push_slice(form).unwrap();
+		quasiquote(stack,&mut eval)
+	}
+
+	#[test]
+	fn unquote_and_splice() {
+		let mut stack = ArrayStack::<_,64>::new();
+
+		//`(a ,x ,@l b)
+		run(&mut stack.view(),&[
+			QQ,
+				Token(A),UQ,Token(X),Cons(2),UQS,Token(L),Cons(2),Token(B),Cons(8),
+			Cons(10),
+		]).unwrap();
+
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[
+			Token(A),Int(5),Int(1),Int(2),Int(3),Token(B),Cons(6),
+		]);
+		stack.flush_all();
+
+		//`(a ,@empty b)
+		run(&mut stack.view(),&[QQ,Token(A),UQS,Token(EMPTY),Cons(2),Token(B),Cons(5),Cons(7)]).unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[Token(A),Token(B),Cons(2)]);
+	}
+
+	#[test]
+	fn splice_nested_objects() {
+		let mut stack = ArrayStack::<_,64>::new();
+
+		//`(,@nested ,@l) and `#(a ,@nested)
+		run(&mut stack.view(),&[
+			QQ,
+				UQS,Token(NESTED),Cons(2),UQS,Token(L),Cons(2),Cons(6),
+			Cons(8),
+		]).unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[
+			Int(1),Int(2),Cons(2),Int(3),Int(1),Vector(2),Int(1),Int(2),Int(3),Cons(9),
+		]);
+		stack.flush_all();
+
+		run(&mut stack.view(),&[
+			QQ,
+				Token(A),UQS,Token(NESTED),Cons(2),Int(2),Vector(5),
+			Cons(7),
+		]).unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[
+			Token(A),Int(1),Int(2),Cons(2),Int(3),Int(1),Vector(2),Int(3),Vector(8),
+		]);
+	}
+
+	#[test]
+	fn nested_levels() {
+		let mut stack = ArrayStack::<_,64>::new();
+
+		//`(a `(b ,(x ,num))) => (a `(b ,(x 7)))
+		let inner = [
+			QQ,
+				Token(B),
+				UQ,Token(X),UQ,Token(NUM),Cons(2),Cons(4),Cons(6),
+				Cons(8),
+			Cons(10),
+		];
+		let mut form = [Nil;15];
+		form[0] = QQ;
+		form[1] = Token(A);
+		form[2..13].copy_from_slice(&inner);
+		form[13] = Cons(12);
+		form[14] = Cons(14);
+
+		run(&mut stack.view(),&form).unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[
+			Token(A),
+				QQ,
+					Token(B),
+					UQ,Token(X),Int(7),Cons(2),Cons(4),Cons(6),
+				Cons(8),
+			Cons(10),
+		]);
+	}
+
+	#[test]
+	fn bad_splices() {
+		let mut stack = ArrayStack::<_,64>::new();
+
+		//`(,@x) with x not a list
+		let err = run(&mut stack.view(),&[QQ,UQS,Token(X),Cons(2),Cons(3),Cons(5)]);
+		assert!(matches!(err,Err(Error::TypeError)));
+		stack.flush_all();
+
+		//`,@l
+		let err = run(&mut stack.view(),&[QQ,UQS,Token(L),Cons(2),Cons(4)]);
+		assert!(matches!(err,Err(Error::SyntaxError)));
+	}
+}
@@ -0,0 +1,291 @@
+use crate::chars::parse_char_literal;
+use crate::sym::{self, SymbolTable};
+use crate::value::{Error, ValueStack, ValueTag};
+
+/*
+ * the reader turns source text into forms on the value stack
+ *
+ * elements of a list are read one after the other straight onto the stack
+ * and the header is pushed once the closing paren shows up,
+ * so a form is never moved while it is read.
+ *
+ * symbol names are not copied, the symbol table borrows them from the source.
+ * there is no string kind yet so `"` is a syntax error.
+ */
+
+pub struct Reader<'t,'a> {
+	pub symbols: &'t mut SymbolTable<'a>,
+	/// bound on how deep lists and quotes may nest
+	pub max_depth: usize,
+}
+
+fn is_delimiter(c:char) -> bool {
+	c.is_whitespace() || matches!(c,'('|')'|';'|'"')
+}
+
+/// skips whitespace and line comments
+fn skip_space(src:&str) -> &str {
+	let mut src = src.trim_start();
+	while let Some(comment) = src.strip_prefix(';') {
+		src = comment.find('\n').map_or("",|i| &comment[i..]).trim_start();
+	}
+	src
+}
+
+/// splits the text up to the next delimiter off the front
+fn split_atom(src:&str) -> (&str,&str) {
+	src.split_at(src.find(is_delimiter).unwrap_or(src.len()))
+}
+
+/// anything with a digit up front, maybe after a sign and a dot, has to be a number
+fn number(text:&str) -> Option<Result<ValueTag,Error>> {
+	let body = text.strip_prefix(['+','-']).unwrap_or(text);
+	let body = body.strip_prefix('.').unwrap_or(body);
+	if !body.starts_with(|c:char| c.is_ascii_digit()) {
+		return None
+	}
+
+	Some(text.parse().map(ValueTag::Int)
+		.or_else(|_| text.parse().map(ValueTag::Float))
+		.map_err(|_| Error::SyntaxError))
+}
+
+fn push(stack:&mut ValueStack,v:ValueTag) -> Result<(),Error>{
+	stack.push(v).map_err(|_| Error::StackOverflow)
+}
+
+impl<'a> Reader<'_,'a> {
+	/// reads one datum off the front of src and pushes it, returns the text after it
+	/// on failure nothing of it is left on the stack
+	pub fn read(&mut self,stack:&mut ValueStack,src:&'a str) -> Result<&'a str,Error>{
+		let start = stack.write_index();
+		let ans = self.datum(stack,src,0);
+		if ans.is_err() {
+			stack.flush(stack.write_index()-start);
+		}
+		ans
+	}
+
+	fn datum(&mut self,stack:&mut ValueStack,src:&'a str,depth:usize) -> Result<&'a str,Error>{
+		if depth > self.max_depth {
+			return Err(Error::DepthLimit)
+		}
+
+		let src = skip_space(src);
+		match src.chars().next() {
+			None | Some(')') | Some('"') => Err(Error::SyntaxError),
+			Some('(') => self.list(stack,&src[1..],depth),
+			Some('\'') => self.wrapped(stack,sym::QUOTE,&src[1..],depth),
+			Some('`') => self.wrapped(stack,sym::QUASIQUOTE,&src[1..],depth),
+			Some(',') => match src[1..].strip_prefix('@') {
+				Some(rest) => self.wrapped(stack,sym::UNQUOTE_SPLICING,rest,depth),
+				None => self.wrapped(stack,sym::UNQUOTE,&src[1..],depth),
+			},
+			Some('#') => self.hash(stack,&src[1..],depth),
+			Some(_) => self.atom(stack,src),
+		}
+	}
+
+	/// `'x` is read as (quote x), `` `x `` `,x` and `,@x` likewise
+	fn wrapped(&mut self,stack:&mut ValueStack,tag:u16,src:&'a str,depth:usize) -> Result<&'a str,Error>{
+		let start = stack.write_index();
+		push(stack,ValueTag::Token(tag))?;
+		let rest = self.datum(stack,src,depth+1)?;
+		let slots = stack.write_index()-start;
+		push(stack,ValueTag::Cons(slots))?;
+		Ok(rest)
+	}
+
+	/// reads elements up to the closing paren
+	/// returns how many there were and the text after the paren
+	fn items(&mut self,stack:&mut ValueStack,src:&'a str,depth:usize) -> Result<(usize,&'a str),Error>{
+		let mut src = src;
+		let mut count = 0;
+		loop {
+			src = skip_space(src);
+			if let Some(rest) = src.strip_prefix(')') {
+				return Ok((count,rest))
+			}
+			src = self.datum(stack,src,depth+1)?;
+			count += 1;
+		}
+	}
+
+	fn list(&mut self,stack:&mut ValueStack,src:&'a str,depth:usize) -> Result<&'a str,Error>{
+		let start = stack.write_index();
+		let (count,rest) = self.items(stack,src,depth)?;
+		let head = match count {
+			0 => ValueTag::Nil,
+			_ => ValueTag::Cons(stack.write_index()-start),
+		};
+		push(stack,head)?;
+		Ok(rest)
+	}
+
+	/// `#(...)` is laid out like a list with the element count under the header, see vector.rs
+	fn vector(&mut self,stack:&mut ValueStack,src:&'a str,depth:usize) -> Result<&'a str,Error>{
+		let start = stack.write_index();
+		let (count,rest) = self.items(stack,src,depth)?;
+		let slots = stack.write_index()-start;
+		stack.push_n([ValueTag::Int(count as i64),ValueTag::Vector(slots+1)])
+			.map_err(|_| Error::StackOverflow)?;
+		Ok(rest)
+	}
+
+	/// `#t`, `#f`, char literals and vectors
+	fn hash(&mut self,stack:&mut ValueStack,src:&'a str,depth:usize) -> Result<&'a str,Error>{
+		if let Some(items) = src.strip_prefix('(') {
+			return self.vector(stack,items,depth)
+		}
+		if let Some(lit) = src.strip_prefix('\\') {
+			//the first char is taken as is so `#\(` and `#\ ` work
+			let first = lit.chars().next().ok_or(Error::SyntaxError)?.len_utf8();
+			let (more,rest) = split_atom(&lit[first..]);
+			let c = parse_char_literal(&lit[..first+more.len()]).ok_or(Error::SyntaxError)?;
+			push(stack,ValueTag::Char(c))?;
+			return Ok(rest)
+		}
+
+		let (text,rest) = split_atom(src);
+		let v = match text {
+			"t" => ValueTag::Bool(true),
+			"f" => ValueTag::Bool(false),
+			_ => return Err(Error::SyntaxError),
+		};
+		push(stack,v)?;
+		Ok(rest)
+	}
+
+	fn atom(&mut self,stack:&mut ValueStack,src:&'a str) -> Result<&'a str,Error>{
+		let (text,rest) = split_atom(src);
+		let v = match number(text) {
+			Some(n) => n?,
+			None => ValueTag::Token(self.symbols.intern(text)?),
+		};
+		push(stack,v)?;
+		Ok(rest)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ValueTag::*;
+	use crate::array_stack::ArrayStack;
+	use crate::stack::make_storage;
+
+	const A: u16 = sym::FIRST_FREE;
+	const B: u16 = sym::FIRST_FREE+1;
+
+	#[test]
+	fn lists_and_atoms() {
+		let mut names = make_storage::<_,16>();
+		let mut symbols = SymbolTable::new(&mut names).unwrap();
+		let mut reader = Reader{symbols:&mut symbols,max_depth:8};
+		let mut stack = ArrayStack::<_,32>::new();
+
+		let rest = reader.read(&mut stack.view(),"(a -12 (2.5 #t) 'a ()) b").unwrap();
+		assert_eq!(rest," b");
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[
+			Token(A),Int(-12),Float(2.5),Bool(true),Cons(2),Token(sym::QUOTE),Token(A),Cons(2),Nil,Cons(9),
+		]);
+		stack.flush_all();
+
+		let rest = reader.read(&mut stack.view(),rest).unwrap();
+		assert_eq!((rest,stack.pop()),("",Some(Token(B))));
+
+		reader.read(&mut stack.view(),"; a comment\n  .5 ; another").unwrap();
+		assert_eq!(stack.pop(),Some(Float(0.5)));
+		assert_eq!(reader.symbols.find("quote"),Some(sym::QUOTE));
+	}
+
+	#[test]
+	fn char_literals() {
+		let mut names = make_storage::<_,8>();
+		let mut symbols = SymbolTable::new(&mut names).unwrap();
+		let mut reader = Reader{symbols:&mut symbols,max_depth:8};
+		let mut stack = ArrayStack::<_,16>::new();
+
+		reader.read(&mut stack.view(),r"(#\a #\space #\x41 #\( #\λ)").unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[
+			Char('a'),Char(' '),Char('A'),Char('('),Char('λ'),Cons(5),
+		]);
+		stack.flush_all();
+
+		assert_eq!(reader.read(&mut stack.view(),r"#\spaces"),Err(Error::SyntaxError));
+		assert_eq!(reader.read(&mut stack.view(),r"#\"),Err(Error::SyntaxError));
+	}
+
+	#[test]
+	fn vectors() {
+		let mut names = make_storage::<_,8>();
+		let mut symbols = SymbolTable::new(&mut names).unwrap();
+		let mut reader = Reader{symbols:&mut symbols,max_depth:8};
+		let mut stack = ArrayStack::<_,16>::new();
+
+		reader.read(&mut stack.view(),"#(1 (2 3) #() a)").unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[
+			Int(1),Int(2),Int(3),Cons(2),Int(0),Vector(1),Token(A),Int(4),Vector(8),
+		]);
+
+		//indexing works on what was read
+		stack.push(Int(1)).unwrap();
+		crate::vector::vector_ref(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[Int(2),Int(3),Cons(2)]);
+		stack.flush_all();
+
+		assert_eq!(reader.read(&mut stack.view(),"#(1 2"),Err(Error::SyntaxError));
+		assert_eq!(stack.len(),0);
+	}
+
+	#[test]
+	fn quasiquote_shorthand() {
+		let mut names = make_storage::<_,16>();
+		let mut symbols = SymbolTable::new(&mut names).unwrap();
+		let mut reader = Reader{symbols:&mut symbols,max_depth:8};
+		let mut stack = ArrayStack::<_,32>::new();
+
+		//a is 5 and b is (1 2)
+		reader.read(&mut stack.view(),"`(a ,a ,@b `,b)").unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[
+			Token(sym::QUASIQUOTE),
+				Token(A),
+				Token(sym::UNQUOTE),Token(A),Cons(2),
+				Token(sym::UNQUOTE_SPLICING),Token(B),Cons(2),
+				Token(sym::QUASIQUOTE),Token(sym::UNQUOTE),Token(B),Cons(2),Cons(4),
+			Cons(12),
+			Cons(14),
+		]);
+
+		crate::quasi::quasiquote(&mut stack.view(),&mut |stack:&mut ValueStack| {
+			let value: &[ValueTag] = match stack.pop() {
+				Some(Token(A)) => &[Int(5)],
+				Some(Token(B)) => &[Int(1),Int(2),Cons(2)],
+				_ => return Err(Error::UnboundSymbol),
+			};
+			stack.push_slice(value).map_err(|_| Error::StackOverflow)
+		}).unwrap();
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[
+			Token(A),Int(5),Int(1),Int(2),
+			Token(sym::QUASIQUOTE),Token(sym::UNQUOTE),Token(B),Cons(2),Cons(4),
+			Cons(9),
+		]);
+	}
+
+	#[test]
+	fn bad_input_leaves_nothing() {
+		let mut names = make_storage::<_,8>();
+		let mut symbols = SymbolTable::new(&mut names).unwrap();
+		let mut reader = Reader{symbols:&mut symbols,max_depth:2};
+		let mut stack = ArrayStack::<_,8>::new();
+		stack.push(Nil).unwrap();
+
+		assert_eq!(reader.read(&mut stack.view(),")"),Err(Error::SyntaxError));
+		assert_eq!(reader.read(&mut stack.view(),"(1 2"),Err(Error::SyntaxError));
+		assert_eq!(reader.read(&mut stack.view(),"(1x)"),Err(Error::SyntaxError));
+		assert_eq!(reader.read(&mut stack.view(),"\"s\""),Err(Error::SyntaxError));
+		assert_eq!(reader.read(&mut stack.view(),"(((1)))"),Err(Error::DepthLimit));
+		assert_eq!(reader.read(&mut stack.view(),"(1 2 3 4 5 6 7)"),Err(Error::StackOverflow));
+		assert_eq!(stack.peek_many(stack.len()).unwrap(),&[Nil]);
+	}
+}
@@ -1,23 +1,43 @@
 use core::{
     marker::PhantomData,
-    mem::MaybeUninit,
+    mem::{ManuallyDrop, MaybeUninit},
+    ptr,
     slice,
 };
+use crate::stack::{CloseGap, Drain, Stack, Window, WindowMut};
 
 /* --------------------------------------------------------------------- */
 /*  Reversed (grow-down) stack                                           */
 /* --------------------------------------------------------------------- */
 
+// The top sits at the lowest live address. Runs of elements going in or out
+// are in stack order like on `StackRef`, the last one is the top, so the live
+// part is read back through a `Window` rather than a plain slice.
+
 pub struct RevStackRef<'a, T> {
     base: *mut T,   // start of allocation
     cap:  usize,    // total slots
     len:  usize,    // live elements
+    #[cfg(feature = "high-water")]
+    peak: usize,    // most live elements seen
+    // For a stack made by `split`: the peak at the bottom of the splits and
+    // how many elements sit below this one there, null otherwise.
+    #[cfg(feature = "high-water")]
+    outer: (*mut usize, usize),
     _p:   PhantomData<&'a mut T>,
 }
 
 unsafe impl<'a, T: Send> Send for RevStackRef<'a, T> {}
 unsafe impl<'a, T: Sync> Sync for RevStackRef<'a, T> {}
 
+/// Drops whatever is still on the stack.
+impl<T> Drop for RevStackRef<'_, T> {
+    fn drop(&mut self) {
+        let start = self.cap - self.len;
+        unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.base.add(start), self.len)) }
+    }
+}
+
 impl<'a, T> RevStackRef<'a, T> {
     /* ------------- constructors ---------------- */
 
@@ -25,13 +45,25 @@ impl<'a, T> RevStackRef<'a, T> {
         Self { base: buf.as_mut_ptr() as *mut T,
                cap:  buf.len(),
                len:  0,
+               #[cfg(feature = "high-water")]
+               peak: 0,
+               #[cfg(feature = "high-water")]
+               outer: (ptr::null_mut(), 0),
                _p:   PhantomData }
     }
 
-    pub fn new_full(buf: &'a mut [T]) -> Self {
+    /// The values stay owned by `buf`'s owner so they must not need dropping.
+    pub fn new_full(buf: &'a mut [T]) -> Self
+    where
+        T: Copy,
+    {
         Self { base: buf.as_mut_ptr(),
                cap:  buf.len(),
                len:  buf.len(),
+               #[cfg(feature = "high-water")]
+               peak: buf.len(),
+               #[cfg(feature = "high-water")]
+               outer: (ptr::null_mut(), 0),
                _p:   PhantomData }
     }
 
@@ -42,6 +74,47 @@ impl<'a, T> RevStackRef<'a, T> {
     #[inline] pub fn room_left(&self)-> usize { self.cap - self.len }
     #[inline] pub fn write_index(&self) -> usize { self.len }  // kept for compat
 
+    /// Sets the number of live elements.
+    ///
+    /// # Safety
+    /// `idx` must be at most the capacity and the top `idx` slots must be initialized.
+    #[inline]
+    pub unsafe fn set_write_index(&mut self, idx: usize) {
+        debug_assert!(idx <= self.cap);
+        self.len = idx;
+        self.touch();
+    }
+
+    #[inline]
+    fn top(&self) -> *mut T {
+        unsafe { self.base.add(self.cap - self.len) }
+    }
+
+    /// Splits into the live elements, top first as they lie in the buffer, and
+    /// an empty stack over the free slots. Through `Stack::split` they come in
+    /// a `WindowMut` in stack order instead.
+    /// The free part grows toward the live one, so after forgetting it its
+    /// values can be taken in with `set_write_index`.
+    /// Pushes on the free part count towards this stack's peak.
+    pub fn split<'b>(&'b mut self) -> (&'b mut [T], RevStackRef<'b, T>) {
+        #[cfg(feature = "high-water")]
+        let outer = if self.outer.0.is_null() {
+            (&mut self.peak as *mut usize, self.len)
+        } else {
+            (self.outer.0, self.outer.1 + self.len)
+        };
+        let live = unsafe { slice::from_raw_parts_mut(self.top(), self.len) };
+        let free = RevStackRef { base: self.base,
+                                 cap:  self.cap - self.len,
+                                 len:  0,
+                                 #[cfg(feature = "high-water")]
+                                 peak: 0,
+                                 #[cfg(feature = "high-water")]
+                                 outer,
+                                 _p:   PhantomData };
+        (live, free)
+    }
+
     /* ------------- single-element ops ---------- */
 
     pub fn push(&mut self, v: T) -> Result<(), T> {
@@ -51,6 +124,7 @@ impl<'a, T> RevStackRef<'a, T> {
         self.len += 1;
         let idx = self.cap - self.len;
         unsafe { self.base.add(idx).write(v); }
+        self.touch();
         Ok(())
     }
 
@@ -67,13 +141,40 @@ impl<'a, T> RevStackRef<'a, T> {
         if self.len == 0 {
             return None;
         }
-        let idx = self.cap - self.len;
-        unsafe { Some(&*self.base.add(idx)) }
+        unsafe { Some(&*self.top()) }
+    }
+
+    /// Push an array, the last element ends up on top.
+    pub fn push_n<const N: usize>(&mut self, mut v: [T; N]) -> Result<(), [T; N]> {
+        if self.room_left() < N {
+            return Err(v);
+        }
+        v.reverse();
+        self.len += N;
+        unsafe { (self.top() as *mut [T; N]).write(v); }
+        self.touch();
+        Ok(())
+    }
+
+    /// Pop the top `N` items, the top one last.
+    pub fn pop_n<const N: usize>(&mut self) -> Option<[T; N]> {
+        if self.len < N {
+            return None;
+        }
+        let mut v = unsafe { (self.top() as *mut [T; N]).read() };
+        self.len -= N;
+        v.reverse();
+        Some(v)
+    }
+
+    /// Borrow the top `N` items, the top one last.
+    pub fn peek_n<const N: usize>(&self) -> Option<Window<'_, T>> {
+        self.peek_many(N)
     }
 
     /* ------------- bulk helpers ---------------- */
 
-    /// Push an entire slice (`vals[0]` ends up deepest, `vals.last()` on top).
+    /// Push an entire slice, the last element ends up on top.
     pub fn push_many(&mut self, vals: &[T]) -> Result<(), ()>
     where
         T: Clone,                 // need a way to copy the values in
@@ -82,57 +183,173 @@ impl<'a, T> RevStackRef<'a, T> {
             return Err(());
         }
 
-        let n         = vals.len();
-        let dst_start = self.cap - self.len - n;
-
-        unsafe {
-            let dst = self.base.add(dst_start);
-            for (i, v) in vals.iter().rev().enumerate() {
-                dst.add(i).write(v.clone());
-            }
+        // deepest first so a panicking clone leaves only live values counted
+        for v in vals {
+            self.len += 1;
+            unsafe { self.top().write(v.clone()); }
         }
-
-        self.len += n;
+        self.touch();
         Ok(())
     }
 
-    /// Borrow the top `n` items without popping.
-    pub fn peek_many(&self, n: usize) -> Option<&[T]> {
+    /// Borrow the top `n` items without popping, the top one last.
+    pub fn peek_many(&self, n: usize) -> Option<Window<'_, T>> {
         if n > self.len {
             return None;
         }
-        let start = self.cap - self.len;
-        unsafe {
-            Some(slice::from_raw_parts(self.base.add(start), n))
-        }
+        unsafe { Some(Window::new_rev(slice::from_raw_parts(self.top(), n))) }
     }
 
-    /// Pop the top `n` items **without** dropping them, returning a `&mut [T]`
-    /// that lives for as long as the mutable borrow of `self`.
-    ///
-    /// ⚠️ The caller is now responsible for eventually dropping those `T`s.
-    pub fn pop_many<'b>(&'b mut self, n: usize) -> Option<&'b mut [T]>
-    where T :Copy
-    {
+    /// Pop the top `n` items, the guard yields them bottom first like on
+    /// `StackRef`. It drops whatever is not moved out of it and keeps `self`
+    /// borrowed until then.
+    pub fn pop_many<'b>(&'b mut self, n: usize) -> Option<Drain<'b, T>> {
         if n > self.len {
             return None;
         }
-        let start = self.cap - self.len;
+        let start = self.top();
         self.len -= n;
 
+        unsafe { Some(Drain::new_rev(start, n)) }
+    }
+
+    /// Drop `count` items ending `skip` below the top, the ones above move down.
+    /// Same contract as `StackRef::drop_inside`, the top itself is never dropped.
+    pub fn drop_inside(&mut self, skip: usize, count: usize) -> Result<(), ()> {
+        if skip == 0 {
+            return Ok(());
+        }
+        if skip >= self.len || count > skip {
+            return Err(());
+        }
+
+        // depths skip-count+1 ..= skip go, the skip-count+1 above them move
+        let keep = skip + 1 - count;
+        let new_len = self.len - count;
+        unsafe {
+            let top = self.top();
+            let _gap = CloseGap { src: top, dst: top.add(count), count: keep, len: &mut self.len, new_len };
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(top.add(keep), count));
+        }
+        Ok(())
+    }
+
+    /// Push `vals` below the top `skip` items, the last one nearest the top.
+    pub fn insert_inside(&mut self, skip: usize, vals: &[T]) -> Result<(), ()>
+    where
+        T: Clone,
+    {
+        if skip > self.len {
+            return Err(());
+        }
+        self.push_many(vals)?;
+
+        let (live, _) = self.split();
+        live[..vals.len() + skip].rotate_left(vals.len());
+        Ok(())
+    }
+
+    /// Move the `count` items lying below the top `skip` items onto the top.
+    pub fn extract_inside(&mut self, skip: usize, count: usize) -> Result<(), ()> {
+        let moved = skip.checked_add(count).filter(|&m| m <= self.len).ok_or(())?;
+        let (live, _) = self.split();
+        live[..moved].rotate_right(count);
+        Ok(())
+    }
+
+    pub fn flush(&mut self, n: usize) {
+        for _ in 0..n {
+            self.pop();
+        }
+    }
+
+    pub fn flush_all(&mut self) {
+        self.flush(self.len);
+    }
+
+    /* ------------- usage tracking -------------- */
+
+    #[inline]
+    fn touch(&mut self) {
+        #[cfg(feature = "high-water")]
+        {
+            self.peak = self.peak.max(self.len);
+            // The stack split from stays borrowed while this one lives.
+            let (root, below) = self.outer;
+            if !root.is_null() {
+                unsafe { *root = (*root).max(below + self.len) }
+            }
+        }
+    }
+
+    /// Most live elements since creation or the last `reset_peak`.
+    #[cfg(feature = "high-water")]
+    pub fn peak(&self) -> usize { self.peak }
+
+    #[cfg(feature = "high-water")]
+    pub fn reset_peak(&mut self) { self.peak = self.len; }
+
+    /// Fill the free slots with `sentinel` so `painted_peak` can measure usage later.
+    pub fn paint(&mut self, sentinel: T) where T: Copy {
+        let free = self.room_left();
         unsafe {
-            Some(slice::from_raw_parts_mut(self.base.add(start), n))
+            for i in 0..free {
+                self.base.add(i).write(sentinel);
+            }
         }
     }
 
+    /// Most live elements since the last `paint`, read off the buffer.
+    ///
+    /// # Safety
+    /// The stack must have been painted, otherwise free slots may be uninitialized.
+    pub unsafe fn painted_peak(&self, sentinel: &T) -> usize where T: PartialEq {
+        let all = unsafe { slice::from_raw_parts(self.base, self.cap) };
+        let used = all.iter().position(|v| v != sentinel).map_or(0, |i| self.cap - i);
+        used.max(self.len)
+    }
+
     /* ------------- expose raw backing ----------- */
 
+    /// The values still on the stack are left in the buffer undropped.
     pub fn into_slice(self) -> &'a mut [MaybeUninit<T>] {
-        unsafe { slice::from_raw_parts_mut(self.base as *mut MaybeUninit<T>, self.cap) }
+        let me = ManuallyDrop::new(self);
+        unsafe { slice::from_raw_parts_mut(me.base as *mut MaybeUninit<T>, me.cap) }
     }
 }
 
 
+impl<T> Iterator for RevStackRef<'_, T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> { self.pop() }
+}
+
+impl<'a, T> Stack<T> for RevStackRef<'a, T> {
+    type Part<'b> = RevStackRef<'b, T> where Self: 'b;
+
+    fn push(&mut self, v: T) -> Result<(), T> { self.push(v) }
+    fn pop(&mut self) -> Option<T> { self.pop() }
+    fn peek(&self) -> Option<&T> { self.peek() }
+    fn write_index(&self) -> usize { self.len }
+    fn room_left(&self) -> usize { self.room_left() }
+    unsafe fn set_write_index(&mut self, idx: usize) { unsafe { self.set_write_index(idx) } }
+
+    fn push_n<const N: usize>(&mut self, v: [T; N]) -> Result<(), [T; N]> { self.push_n(v) }
+    fn push_slice(&mut self, v: &[T]) -> Result<(), ()> where T: Clone { self.push_many(v) }
+    fn pop_n<const N: usize>(&mut self) -> Option<[T; N]> { self.pop_n() }
+    fn pop_many<'b>(&'b mut self, n: usize) -> Option<Drain<'b, T>> { self.pop_many(n) }
+    fn peek_many<'b>(&'b self, n: usize) -> Option<Window<'b, T>> { self.peek_many(n) }
+    fn drop_inside(&mut self, skip: usize, count: usize) -> Result<(), ()> { self.drop_inside(skip, count) }
+    fn insert_inside(&mut self, skip: usize, v: &[T]) -> Result<(), ()> where T: Clone { self.insert_inside(skip, v) }
+    fn extract_inside(&mut self, skip: usize, count: usize) -> Result<(), ()> { self.extract_inside(skip, count) }
+    fn flush(&mut self, n: usize) { self.flush(n) }
+
+    fn split<'b>(&'b mut self) -> (WindowMut<'b, T>, RevStackRef<'b, T>) {
+        let (live, part) = self.split();
+        (WindowMut::new_rev(live), part)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::stack::make_storage;
@@ -177,12 +394,12 @@ use super::*;
         assert_eq!(s.len(), 4);
 
         let peeked = s.peek_many(2).unwrap();
-        assert_eq!(peeked, &[40,30]);
+        assert_eq!(peeked, [30, 40]);
+        assert_eq!((peeked[1], s.peek()), (40, Some(&40)));
 
-        //order may seem odd here but [30 40] must be ontop
-        //20 is the highest element of the previous slice so it must be the last
+        //runs keep stack order just like on StackRef, the top comes last
         let popped = s.pop_many(3).unwrap();
-        assert_eq!(popped, &[40,30, 20]);
+        assert_eq!(popped.collect::<std::vec::Vec<_>>(), [20, 30, 40]);
 
         assert_eq!(s.len(), 1);
         assert_eq!(s.peek(), Some(&10));
@@ -196,5 +413,256 @@ use super::*;
         assert!(s.is_empty());
     }
 
+    #[test]
+    fn painted_peak_counts_from_the_top() {
+        let mut storage = make_storage::<u16, 6>();
+        let mut s = RevStackRef::from_slice(&mut storage);
+        s.push(1).unwrap();
+        s.paint(0xffff);
+
+        s.push_many(&[2, 3, 4]).unwrap();
+        s.pop_many(3).unwrap();
+        assert_eq!(unsafe { s.painted_peak(&0xffff) }, 4);
+
+        s.paint(0xffff);
+        assert_eq!(unsafe { s.painted_peak(&0xffff) }, 1);
+    }
+
+    #[cfg(feature = "high-water")]
+    #[test]
+    fn high_water() {
+        let mut storage = make_storage::<u16, 6>();
+        let mut s = RevStackRef::from_slice(&mut storage);
+
+        s.push_many(&[1, 2, 3]).unwrap();
+        s.pop().unwrap();
+        s.push(4).unwrap();
+        assert_eq!(s.peak(), 3);
+
+        s.reset_peak();
+        s.pop().unwrap();
+        assert_eq!(s.peak(), 3);
+        s.reset_peak();
+        assert_eq!(s.peak(), 2);
+
+        // Pushes through split count too.
+        let (_, mut free) = s.split();
+        free.push_many(&[5, 6, 7]).unwrap();
+        let (_, mut inner) = free.split();
+        inner.push(8).unwrap();
+        drop(inner);
+        drop(free);
+        assert_eq!(s.peak(), 6);
+    }
+
+    #[test]
+    fn drops_exactly_once() {
+        use core::cell::Cell;
+
+        struct Counted<'c>(&'c Cell<usize>);
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) { self.0.set(self.0.get() + 1); }
+        }
+
+        let drops = Cell::new(0);
+        let mut storage = make_storage::<Counted, 4>();
+        let mut s = RevStackRef::from_slice(&mut storage);
+        for _ in 0..4 {
+            assert!(s.push(Counted(&drops)).is_ok());
+        }
+
+        drop(s.pop());
+        assert_eq!(drops.get(), 1);
+        let mut popped = s.pop_many(2).unwrap();
+        drop(popped.next());
+        assert_eq!(drops.get(), 2);
+        drop(popped);
+        assert_eq!(drops.get(), 3);
+        drop(s);
+        assert_eq!(drops.get(), 4);
+    }
+
+    #[test]
+    fn panicking_drop_drops_once() {
+        use core::cell::Cell;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        // the first armed one to be dropped panics
+        struct Bomb<'c>(bool, &'c Cell<usize>, &'c Cell<bool>);
+        impl Drop for Bomb<'_> {
+            fn drop(&mut self) {
+                self.1.set(self.1.get() + 1);
+                if self.0 && self.2.replace(false) {
+                    panic!("bomb");
+                }
+            }
+        }
+
+        let drops = Cell::new(0);
+        let fuse = Cell::new(true);
+        let mut storage = make_storage::<Bomb, 6>();
+        let mut s = RevStackRef::from_slice(&mut storage);
+        for i in 0..5 {
+            assert!(s.push(Bomb(i == 2, &drops, &fuse)).is_ok());
+        }
+
+        // removes 1 and 2, dropping 2 panics
+        assert!(catch_unwind(AssertUnwindSafe(|| s.drop_inside(3, 2))).is_err());
+        assert_eq!((drops.get(), s.len()), (2, 3));
+        drop(s);
+        assert_eq!(drops.get(), 5);
+    }
+
+    #[test]
+    fn zero_sized_elements() {
+        let mut storage = make_storage::<(), 2>();
+        let mut s = RevStackRef::from_slice(&mut storage);
+        s.push_many(&[(), ()]).unwrap();
+        assert!(s.push(()).is_err());
+        assert_eq!(s.pop_many(2).map(|d| d.len()), Some(2));
+        assert!(s.pop().is_none());
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Push(u32),
+        PushMany(std::vec::Vec<u32>),
+        PushN([u32; 3]),
+        Pop,
+        PopN,
+        PopMany(usize),
+        PeekN,
+        PeekMany(usize),
+        DropInside(usize, usize),
+        InsertInside(usize, std::vec::Vec<u32>),
+        ExtractInside(usize, usize),
+        Flush(usize),
+    }
+
+    fn op_strategy() -> impl proptest::strategy::Strategy<Value = Op> {
+        use proptest::prelude::*;
+        //past ten arms prop_oneof builds a vec
+        use std::vec;
+        prop_oneof![
+            any::<u32>().prop_map(Op::Push),
+            proptest::collection::vec(any::<u32>(), 0..6).prop_map(Op::PushMany),
+            any::<[u32; 3]>().prop_map(Op::PushN),
+            Just(Op::Pop),
+            Just(Op::PopN),
+            (0..8usize).prop_map(Op::PopMany),
+            Just(Op::PeekN),
+            (0..8usize).prop_map(Op::PeekMany),
+            (0..8usize, 0..8usize).prop_map(|(s, c)| Op::DropInside(s, c)),
+            (0..8usize, proptest::collection::vec(any::<u32>(), 0..4)).prop_map(|(s, v)| Op::InsertInside(s, v)),
+            (0..8usize, 0..8usize).prop_map(|(s, c)| Op::ExtractInside(s, c)),
+            (0..4usize).prop_map(Op::Flush),
+        ]
+    }
+
+    /// the model keeps the top at the end and so does every run the stack hands out
+    fn check_against_vec(ops: &[Op], cap: usize) {
+        use std::vec::Vec;
+
+        let mut storage: Vec<MaybeUninit<u32>> = (0..cap).map(|_| MaybeUninit::uninit()).collect();
+        let mut s = RevStackRef::from_slice(&mut storage);
+        let mut model: Vec<u32> = Vec::new();
+
+        for op in ops {
+            let len = model.len();
+            match op {
+                Op::Push(x) => {
+                    let r = s.push(*x).is_ok();
+                    assert_eq!(r, len < cap);
+                    if r { model.push(*x) }
+                }
+                Op::PushMany(xs) => {
+                    let r = s.push_many(xs).is_ok();
+                    assert_eq!(r, len + xs.len() <= cap);
+                    if r { model.extend(xs) }
+                }
+                Op::PushN(xs) => {
+                    let r = s.push_n(*xs).is_ok();
+                    assert_eq!(r, len + 3 <= cap);
+                    if r { model.extend(xs) }
+                }
+                Op::Pop => assert_eq!(s.pop(), model.pop()),
+                Op::PopN => {
+                    let got = s.pop_n::<2>();
+                    assert_eq!(got.is_some(), len >= 2);
+                    if let Some(got) = got {
+                        assert_eq!(got[..], model[len - 2..]);
+                        model.truncate(len - 2);
+                    }
+                }
+                Op::PeekN => {
+                    let got = s.peek_n::<2>();
+                    assert_eq!(got.is_some(), len >= 2);
+                    if let Some(got) = got {
+                        assert_eq!(got, model[len - 2..]);
+                    }
+                }
+                Op::DropInside(skip, count) => {
+                    let r = s.drop_inside(*skip, *count).is_ok();
+                    assert_eq!(r, *skip == 0 || (*skip < len && count <= skip));
+                    if r && *skip != 0 {
+                        let spot = len - 1 - skip;
+                        model.drain(spot..spot + count);
+                    }
+                }
+                Op::InsertInside(skip, xs) => {
+                    let r = s.insert_inside(*skip, xs).is_ok();
+                    assert_eq!(r, *skip <= len && len + xs.len() <= cap);
+                    if r {
+                        let at = len - skip;
+                        model.splice(at..at, xs.iter().copied());
+                    }
+                }
+                Op::ExtractInside(skip, count) => {
+                    let r = s.extract_inside(*skip, *count).is_ok();
+                    assert_eq!(r, skip + count <= len);
+                    if r {
+                        let at = len - skip - count;
+                        let moved: Vec<u32> = model.drain(at..at + count).collect();
+                        model.extend(moved);
+                    }
+                }
+                Op::Flush(n) => {
+                    s.flush(*n);
+                    model.truncate(len.saturating_sub(*n));
+                }
+                Op::PopMany(n) => {
+                    let got: Option<Vec<u32>> = s.pop_many(*n).map(|d| d.collect());
+                    assert_eq!(got.is_some(), *n <= len);
+                    if let Some(got) = got {
+                        assert_eq!(got, model[len - n..]);
+                        model.truncate(len - n);
+                    }
+                }
+                Op::PeekMany(n) => {
+                    let got = s.peek_many(*n);
+                    assert_eq!(got.is_some(), *n <= len);
+                    if let Some(got) = got {
+                        assert_eq!(got, model[len - n..]);
+                    }
+                }
+            }
+            assert_eq!(s.len(), model.len());
+            assert_eq!(s.room_left(), cap - model.len());
+            assert_eq!(s.peek(), model.last());
+            assert_eq!(s.peek_many(model.len()).unwrap(), model[..]);
+        }
+    }
+
+    proptest::proptest! {
+        // miri has no file system for the failure log and runs a few cases at most
+        #![proptest_config(proptest::test_runner::Config {
+            cases: if cfg!(miri) { 4 } else { proptest::test_runner::Config::default().cases },
+            failure_persistence: if cfg!(miri) { None } else { proptest::test_runner::Config::default().failure_persistence },
+            ..proptest::test_runner::Config::default()
+        })]
+        #[test]
+        fn matches_vec_model(ops in proptest::collection::vec(op_strategy(), 0..64), cap in 0..12usize) {
+            check_against_vec(&ops, cap);
+        }
+    }
 }
-    
\ No newline at end of file
@@ -1,7 +1,9 @@
+use core::fmt;
 use core::ptr;
 use core::slice;
-use core::mem::MaybeUninit;
+use core::mem::{ManuallyDrop, MaybeUninit};
 use core::marker::PhantomData;
+use core::ops::{Index, IndexMut};
 
 pub fn make_storage<T,const SIZE:usize>() ->[MaybeUninit<T>;SIZE]{
     [const { MaybeUninit::uninit() };SIZE]
@@ -39,10 +41,22 @@ fn test_take_last() {
 
 
 
+/// all bookkeeping is in element counts from base so nothing overflows
+/// and zero sized types work, pointers only get formed in bounds
 pub struct StackRef<'a, T> {
     base: *mut T,
-    head: *mut T,
-    end: *mut T,
+    /// the write index
+    len: usize,
+    cap: usize,
+    /// everything below this was written at some point, see try_set_write_index
+    init: usize,
+    /// highest write index seen, see peak
+    #[cfg(feature = "high-water")]
+    peak: usize,
+    /// for a stack made by split, the peak of the stack at the bottom of the splits
+    /// and how far below this one's base it starts, null otherwise
+    #[cfg(feature = "high-water")]
+    outer: (*mut usize,usize),
 
     _phantom:PhantomData<&'a mut T>
 }
@@ -50,6 +64,22 @@ pub struct StackRef<'a, T> {
 unsafe impl<'a, T: Send> Send for StackRef<'a, T> {}
 unsafe impl<'a, T: Sync> Sync for StackRef<'a, T> {}
 
+/// the stack owns everything pushed on it and drops what is left with it
+impl<T> Drop for StackRef<'_, T>{
+    fn drop(&mut self){
+        unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.base,self.write_index())) }
+    }
+}
+
+/// an empty stack over the buffer, same as from_slice
+impl<'a, T> From<&'a mut [MaybeUninit<T>]> for StackRef<'a, T>{
+    fn from(mem:&'a mut [MaybeUninit<T>]) -> Self { Self::from_slice(mem) }
+}
+
+impl<'a, T, const N:usize> From<&'a mut [MaybeUninit<T>;N]> for StackRef<'a, T>{
+    fn from(mem:&'a mut [MaybeUninit<T>;N]) -> Self { Self::from_slice(mem) }
+}
+
 impl<T> Iterator for StackRef<'_, T>{
 
 type Item = T;
@@ -58,14 +88,19 @@ fn next(&mut self) -> Option<T> { self.pop() }
 
 
 impl<'a, T> StackRef<'a, T>{
+    /// the values stay owned by whoever owns mem so they must not need dropping
     #[inline]
-    pub fn new_full(mem:&'a mut [T]) -> Self{
-        let base = mem.as_mut_ptr();
-        let end = unsafe {base.add(mem.len())}; 
+    pub fn new_full(mem:&'a mut [T]) -> Self
+    where T: Copy {
         Self{
-            base,
-            head: end,
-            end,
+            base: mem.as_mut_ptr(),
+            len: mem.len(),
+            cap: mem.len(),
+            init: mem.len(),
+            #[cfg(feature = "high-water")]
+            peak: mem.len(),
+            #[cfg(feature = "high-water")]
+            outer: (ptr::null_mut(),0),
 
             _phantom:PhantomData,
         }
@@ -73,39 +108,35 @@ impl<'a, T> StackRef<'a, T>{
 
     #[inline]
     pub fn from_slice(mem:&'a mut [MaybeUninit<T>]) -> Self{
-        let base = mem.as_mut_ptr() as _;
         Self{
-            base,
-            head: base,
-            end: unsafe {base.add(mem.len())},
+            base: mem.as_mut_ptr() as _,
+            len: 0,
+            cap: mem.len(),
+            init: 0,
+            #[cfg(feature = "high-water")]
+            peak: 0,
+            #[cfg(feature = "high-water")]
+            outer: (ptr::null_mut(),0),
 
             _phantom:PhantomData,
         }
     }
 
     #[inline]
+    /// gives the buffer back, the values still on the stack are left in it undropped
     pub fn to_slice(self) -> &'a mut [MaybeUninit<T>] {
-        unsafe { 
-            let len = self.end.offset_from(self.base) as usize; 
-            let p = ptr::slice_from_raw_parts_mut(self.base,len);
-            &mut *(p as *mut [MaybeUninit<T>])
-        }
+        let me = ManuallyDrop::new(self);
+        unsafe { &mut *ptr::slice_from_raw_parts_mut(me.base as *mut MaybeUninit<T>,me.cap) }
     }
 
     #[inline]
     pub fn as_slice<'b>(&'b mut self)-> &'b mut [MaybeUninit<T>] {
-        unsafe { 
-            let len = self.end.offset_from(self.base) as usize; 
-            let p = ptr::slice_from_raw_parts_mut(self.base,len);
-            &mut *(p as *mut [MaybeUninit<T>])
-        }
+        unsafe { &mut *ptr::slice_from_raw_parts_mut(self.base as *mut MaybeUninit<T>,self.cap) }
     }
 
     #[inline]
     pub fn room_left(&self) -> usize {
-        unsafe { 
-            self.end.offset_from(self.head).try_into().unwrap() 
-        } 
+        self.cap - self.len
     }
 
     /// returns the index the index the writing head points to
@@ -113,27 +144,83 @@ impl<'a, T> StackRef<'a, T>{
     ///        ^
     #[inline]
     pub fn write_index(&self) -> usize {
-        unsafe { self.head.offset_from(self.base) as usize }
+        self.len
     }
 
     /// sets the write index retrived from write_index
     /// note that the memory below that index is assumed inilized 
+    ///
+    /// # Safety
+    /// idx must be within the buffer and every slot below it must be initialized
     #[inline    ]
-    pub unsafe fn set_write_index(&mut self,idx:usize){ unsafe {
-        self.head = self.base.add(idx)
-    }}
+    pub unsafe fn set_write_index(&mut self,idx:usize){
+        debug_assert!(idx <= self.cap);
+        self.len = idx;
+        self.touch();
+    }
 
+    /// # Safety
+    /// same contract as set_write_index for the new index
     #[inline]
     pub unsafe fn advance(&mut self,add:usize){ unsafe {
         self.set_write_index(self.write_index()+add)
     }}
 
+    /// set_write_index that refuses to expose slots that were never written
+    /// values popped earlier are still there so this needs T: Copy
+    pub fn try_set_write_index(&mut self,idx:usize) -> Result<(),()>
+    where T: Copy {
+        if idx > self.init {
+            return Err(())
+        }
+        unsafe { self.set_write_index(idx) }
+        Ok(())
+    }
+
+    pub fn try_advance(&mut self,add:usize) -> Result<(),()>
+    where T: Copy {
+        let idx = self.write_index().checked_add(add).ok_or(())?;
+        self.try_set_write_index(idx)
+    }
+
+    /// the next len free slots to build values in place, hand them over with commit
+    pub fn push_uninit<'b>(&'b mut self,len:usize) -> Option<&'b mut [MaybeUninit<T>]> {
+        if self.room_left() < len {
+            return None
+        }
+        unsafe {
+            Some(&mut *ptr::slice_from_raw_parts_mut(self.head() as *mut MaybeUninit<T>,len))
+        }
+    }
+
+    /// pushes the first len slots handed out by push_uninit
+    ///
+    /// # Safety
+    /// all of them must have been written
+    pub unsafe fn commit(&mut self,len:usize){ unsafe {
+        debug_assert!(len <= self.room_left());
+        self.advance(len)
+    }}
+
     /// splits the stack into a full left part and an empty right part
+    /// the right part owns what is pushed on it, to hand that over to this stack
+    /// forget it before advancing over its values
     pub fn split<'b>(&'b mut self) -> (&'b mut [T],StackRef<'b, T>){
+        //what gets pushed on the right part counts towards our peak too
+        #[cfg(feature = "high-water")]
+        let outer = match self.outer.0.is_null() {
+            true => (&mut self.peak as *mut usize,self.len),
+            false => (self.outer.0,self.outer.1+self.len),
+        };
         let end = StackRef{
-            base:self.head,
-            head:self.head,
-            end:self.end,
+            base:self.head(),
+            len:0,
+            cap:self.room_left(),
+            init:0,
+            #[cfg(feature = "high-water")]
+            peak:0,
+            #[cfg(feature = "high-water")]
+            outer,
 
             _phantom:PhantomData,
 
@@ -147,99 +234,173 @@ impl<'a, T> StackRef<'a, T>{
         //     _phantom:PhantomData,
 
         // };
-        let s = unsafe { 
-            let len = self.head.offset_from(self.base) as usize; 
-            &mut*ptr::slice_from_raw_parts_mut(self.base,len)
-        };
+        let s = unsafe { &mut*ptr::slice_from_raw_parts_mut(self.base,self.len) };
 
         (s,end)
     }
 
+    /// takes the last len free slots off this stack as a separate empty stack
+    /// the two never overlap so both can be used freely
+    pub fn carve(&mut self,len:usize) -> Option<StackRef<'a, T>>{
+        if self.room_left() < len {
+            return None;
+        }
+
+        self.cap -= len;
+        self.init = self.init.min(self.cap);
+        Some(StackRef{
+            base:unsafe { self.base.add(self.cap) },
+            len:0,
+            cap:len,
+            init:0,
+            #[cfg(feature = "high-water")]
+            peak:0,
+            #[cfg(feature = "high-water")]
+            outer:(ptr::null_mut(),0),
+
+            _phantom:PhantomData,
+        })
+    }
+
+    /// gives back a stack carved off this one, only the last carved part fits
+    /// whatever it still holds is discarded
+    pub fn rejoin(&mut self,part:StackRef<'a, T>) -> Result<(),StackRef<'a, T>>{
+        if part.base != unsafe { self.base.add(self.cap) } {
+            return Err(part);
+        }
+        //for zero sized types any part looks like the last one, they are all interchangeable anyway
+        self.cap += part.cap;
+        Ok(())
+    }
+
+    #[inline]
+    fn touch(&mut self){
+        self.init = self.init.max(self.len);
+        #[cfg(feature = "high-water")]
+        {
+            self.peak = self.peak.max(self.len);
+            let (root,below) = self.outer;
+            //the stack split from is borrowed for as long as this one lives
+            if !root.is_null() {
+                unsafe { *root = (*root).max(below+self.len) }
+            }
+        }
+    }
+
+    /// whether this still is a stack over the cap slots at buf
+    pub(crate) fn is_over(&self,buf:*const T,cap:usize) -> bool {
+        ptr::eq(self.base,buf) && self.cap == cap && self.len <= cap
+    }
+
+    /// the first free slot, may be one past the end
+    #[inline]
+    fn head(&self) -> *mut T {
+        unsafe { self.base.add(self.len) }
+    }
+
+    /// the highest write index reached since creation or the last reset_peak
+    #[cfg(feature = "high-water")]
+    pub fn peak(&self) -> usize {
+        self.peak
+    }
+
+    #[cfg(feature = "high-water")]
+    pub fn reset_peak(&mut self){
+        self.peak = self.write_index();
+    }
+
+    /// fills the free space with a sentinel so painted_peak can tell how far it got
+    pub fn paint(&mut self,sentinel:T) where T: Copy {
+        let head = self.head();
+        unsafe {
+            for i in 0..self.room_left() {
+                head.add(i).write(sentinel);
+            }
+        }
+    }
+
+    /// how far the stack reached since it was painted, read off the buffer itself
+    /// values equal to the sentinel left at the very top are not counted
+    ///
+    /// # Safety
+    /// the stack must have been painted, otherwise free slots may be uninitialized
+    pub unsafe fn painted_peak(&self,sentinel:&T) -> usize where T: PartialEq {
+        let all = unsafe { &*ptr::slice_from_raw_parts(self.base,self.cap) };
+        let used = all.iter().rposition(|v| v != sentinel).map_or(0,|i| i+1);
+        used.max(self.write_index())
+    }
+
 
     pub fn push(&mut self,v:T) -> Result<(),T> {
-        if self.head == self.end {
+        if self.len == self.cap {
             return Err(v)
         }
 
-        unsafe{
-            self.head.write(v);
-            self.head=self.head.add(1);
-        }
+        unsafe{ self.head().write(v) }
+        self.len += 1;
+        self.touch();
 
         Ok(())
     }
 
     pub fn push_n<const SIZE:usize>(&mut self,v:[T;SIZE]) -> Result<(),[T;SIZE]> {
-        if SIZE == 0 {
-            return Ok(())
-        }
-        //pointer arithmetic can overflow here
-        if self.head as usize + (SIZE-1)*size_of::<T>() >= self.end as usize {
+        if self.room_left() < SIZE {
             return Err(v)
         }
 
-        unsafe{
-            (self.head as *mut [T;SIZE]).write(v);
-            self.head=self.head.add(SIZE);
-        }
+        unsafe{ (self.head() as *mut [T;SIZE]).write(v) }
+        self.len += SIZE;
+        self.touch();
 
         Ok(())
     }
 
     pub fn push_slice(&mut self,v:&[T]) -> Result<(),()>
     where T : Clone {
-        //pointer arithmetic can overflow here
-        if self.head as usize + (v.len()-1)*size_of::<T>() >= self.end as usize {
+        if self.room_left() < v.len() {
             return Err(())
         }
 
-        unsafe{
-            let spot = &mut*ptr::slice_from_raw_parts_mut(self.head,v.len());
-            spot.clone_from_slice(v);
-            self.head=self.head.add(v.len());
+        //the slots are uninitialized so they are written rather than assigned
+        //len only moves after each write so a panicking clone leaks nothing
+        for x in v {
+            unsafe{ self.head().write(x.clone()) }
+            self.len += 1;
         }
+        self.touch();
 
         Ok(())
     }
 
     pub fn pop(&mut self) -> Option<T> {
-        if self.head == self.base {
+        if self.len == 0 {
             return None;
         }
 
-        unsafe {
-            self.head=self.head.sub(1);
-            let ans = self.head.read();
-            Some(ans)
-        }
+        self.len -= 1;
+        unsafe { Some(self.head().read()) }
     }
 
     pub fn pop_n<const SIZE:usize>(&mut self)->Option<[T;SIZE]>{
-        if (self.head as usize) < self.base as usize + SIZE*size_of::<T>(){
+        if self.len < SIZE {
             return None;
         }
 
-        unsafe {
-            self.head=self.head.sub(SIZE);
-            let ans = (self.head as *mut [T;SIZE]).read();
-            Some(ans)
-        }
+        self.len -= SIZE;
+        unsafe { Some((self.head() as *mut [T;SIZE]).read()) }
     }
 
     #[inline]
-    pub fn pop_many<'b>(&'b mut self,size:usize) -> Option<&'b mut [T]>
-    where T :Copy
+    /// takes the top size elements off in stack order,
+    /// the guard drops whatever is not moved out of it
+    pub fn pop_many<'b>(&'b mut self,size:usize) -> Option<Drain<'b, T>>
     {
-        //we cant do normal arithmetic since it may overflow
-         if (self.head as usize) < self.base as usize + size*size_of::<T>(){
+        if self.len < size {
             return None;
         }
 
-        unsafe {
-            self.head=self.head.sub(size);
-            let p = self.head as *mut T;
-            Some(&mut*ptr::slice_from_raw_parts_mut(p,size))
-        }
+        self.len -= size;
+        unsafe { Some(Drain::new(self.head(),size)) }
     }
 
     //drops starting from skip below the counter taking count upward
@@ -257,13 +418,14 @@ impl<'a, T> StackRef<'a, T>{
         }
 
         let count_move = self.write_index() - start_good;
+        let new_len = self.len - count;
 
         unsafe{
             let p_start = self.base.add(spot);
             let p_good = self.base.add(start_good);
-            ptr::copy(p_good as *const _,p_start,count_move);
-
-            self.head=self.head.sub(count);
+            //the removed values are dropped first so the move only lands on dead slots
+            let _gap = CloseGap{src:p_good,dst:p_start,count:count_move,len:&mut self.len,new_len};
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(p_start,count));
         }
 
         Ok(())
@@ -271,6 +433,29 @@ impl<'a, T> StackRef<'a, T>{
     }
 
 
+    /// pushes v below the top skip elements, so after a drop_inside that left
+    /// skip elements above the gap this puts the dropped ones back
+    pub fn insert_inside(&mut self,skip:usize,v:&[T]) -> Result<(),()>
+    where T: Clone {
+        if skip > self.len {
+            return Err(())
+        }
+        self.push_slice(v)?;
+
+        let start = self.len - skip - v.len();
+        let (live,_) = self.split();
+        live[start..].rotate_right(v.len());
+        Ok(())
+    }
+
+    /// moves the count elements lying below the top skip elements onto the top
+    pub fn extract_inside(&mut self,skip:usize,count:usize) -> Result<(),()>{
+        let start = self.len.checked_sub(skip).and_then(|l| l.checked_sub(count)).ok_or(())?;
+        let (live,_) = self.split();
+        live[start..].rotate_left(count);
+        Ok(())
+    }
+
     #[inline]
     pub fn peek<'b>(&'b self) -> Option<&'b T>{
         self.peek_n::<1>().map(|a| &a[0])
@@ -278,28 +463,20 @@ impl<'a, T> StackRef<'a, T>{
 
     #[inline]
     pub fn peek_n<'b,const SIZE:usize>(&'b self) -> Option<&'b [T;SIZE]>{
-        //we cant do normal arithmetic since it may overflow
-        if (self.head as usize) < self.base as usize + SIZE*size_of::<T>(){
+        if self.len < SIZE {
             return None;
         }
 
-        unsafe {
-            let p = self.head.sub(SIZE) as *const T as *const [T;SIZE];
-            Some(&*p)
-        }
+        unsafe { Some(&*(self.base.add(self.len-SIZE) as *const [T;SIZE])) }
     }
 
     #[inline]
     pub fn peek_many<'b>(&'b self,size:usize) -> Option<&'b [T]>{
-        //we cant do normal arithmetic since it may overflow
-         if (self.head as usize) < self.base as usize + size*size_of::<T>(){
+        if self.len < size {
             return None;
         }
 
-        unsafe {
-            let p = self.head.sub(size) as *const T;
-            Some(slice::from_raw_parts(p,size))
-        }
+        unsafe { Some(slice::from_raw_parts(self.base.add(self.len-size),size)) }
     }
 
     pub fn flush_all(&mut self){
@@ -315,6 +492,339 @@ impl<'a, T> StackRef<'a, T>{
     }
 }
 
+/// what the grow-up and grow-down stacks have in common so code can take either
+///
+/// runs of elements going in and out are in stack order on every backend:
+/// the first is the deepest and the last ends up on or came off the top
+pub trait Stack<T> {
+    /// the empty stack over the free slots that split hands out
+    type Part<'b>: Stack<T> where Self: 'b;
+
+    fn push(&mut self,v:T) -> Result<(),T>;
+    fn pop(&mut self) -> Option<T>;
+    fn peek(&self) -> Option<&T>;
+
+    /// the number of live elements
+    fn write_index(&self) -> usize;
+    fn room_left(&self) -> usize;
+
+    /// # Safety
+    /// idx must be within the buffer and every slot it exposes must be initialized
+    unsafe fn set_write_index(&mut self,idx:usize);
+
+    /// # Safety
+    /// same contract as set_write_index for the new index
+    unsafe fn advance(&mut self,add:usize){
+        unsafe { self.set_write_index(self.write_index()+add) }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.write_index() == 0
+    }
+
+    fn push_n<const SIZE:usize>(&mut self,v:[T;SIZE]) -> Result<(),[T;SIZE]>;
+    fn push_slice(&mut self,v:&[T]) -> Result<(),()> where T: Clone;
+    fn pop_n<const SIZE:usize>(&mut self) -> Option<[T;SIZE]>;
+    fn pop_many<'b>(&'b mut self,size:usize) -> Option<Drain<'b, T>>;
+    fn peek_n<'b,const SIZE:usize>(&'b self) -> Option<Window<'b, T>> {
+        self.peek_many(SIZE)
+    }
+    fn peek_many<'b>(&'b self,size:usize) -> Option<Window<'b, T>>;
+
+    /// drops count elements ending skip below the top, the top itself always stays
+    fn drop_inside(&mut self,skip:usize,count:usize) -> Result<(),()>;
+    /// pushes v below the top skip elements
+    fn insert_inside(&mut self,skip:usize,v:&[T]) -> Result<(),()> where T: Clone;
+    /// moves the count elements lying below the top skip elements onto the top
+    fn extract_inside(&mut self,skip:usize,count:usize) -> Result<(),()>;
+    fn flush(&mut self,len:usize);
+
+    fn flush_all(&mut self){
+        self.flush(self.write_index())
+    }
+
+    /// the live elements and an empty stack over the free slots,
+    /// forget the part before taking its values in with advance
+    fn split<'b>(&'b mut self) -> (WindowMut<'b, T>,Self::Part<'b>);
+}
+
+/// elements borrowed off a stack in stack order, the last one is nearest the top
+/// whichever way the buffer grows
+pub struct Window<'b, T> {
+    items: &'b [T],
+    /// the buffer holds them top first
+    rev: bool,
+}
+
+/// a Window that can change the elements in place
+pub struct WindowMut<'b, T> {
+    items: &'b mut [T],
+    rev: bool,
+}
+
+/// iterates a buffer run in stack order
+pub struct Ordered<I> {
+    inner: I,
+    rev: bool,
+}
+
+impl<I:DoubleEndedIterator> Iterator for Ordered<I> {
+    type Item = I::Item;
+    fn next(&mut self) -> Option<I::Item> {
+        match self.rev {
+            false => self.inner.next(),
+            true => self.inner.next_back(),
+        }
+    }
+}
+
+impl<I:DoubleEndedIterator> DoubleEndedIterator for Ordered<I> {
+    fn next_back(&mut self) -> Option<I::Item> {
+        match self.rev {
+            false => self.inner.next_back(),
+            true => self.inner.next(),
+        }
+    }
+}
+
+/// where the k-th element in stack order sits in a buffer run of len
+#[inline]
+fn ordered_index(k:usize,len:usize,rev:bool) -> usize {
+    match rev {
+        false => k,
+        true => len-1-k,
+    }
+}
+
+impl<'b, T> Window<'b, T> {
+    /// elements lying bottom first in the buffer
+    pub fn new(items:&'b [T]) -> Self {
+        Self{items,rev:false}
+    }
+
+    /// elements lying top first in the buffer
+    pub fn new_rev(items:&'b [T]) -> Self {
+        Self{items,rev:true}
+    }
+
+    pub fn len(&self) -> usize { self.items.len() }
+    pub fn is_empty(&self) -> bool { self.items.is_empty() }
+
+    /// the k-th element counting from the deepest one
+    pub fn get(&self,k:usize) -> Option<&'b T> {
+        (k < self.len()).then(|| &self.items[ordered_index(k,self.len(),self.rev)])
+    }
+
+    pub fn iter(&self) -> Ordered<slice::Iter<'b, T>> {
+        Ordered{inner:self.items.iter(),rev:self.rev}
+    }
+}
+
+impl<'b, T> WindowMut<'b, T> {
+    pub fn new(items:&'b mut [T]) -> Self {
+        Self{items,rev:false}
+    }
+
+    pub fn new_rev(items:&'b mut [T]) -> Self {
+        Self{items,rev:true}
+    }
+
+    pub fn len(&self) -> usize { self.items.len() }
+    pub fn is_empty(&self) -> bool { self.items.is_empty() }
+
+    pub fn get_mut(&mut self,k:usize) -> Option<&mut T> {
+        let len = self.len();
+        (k < len).then(|| &mut self.items[ordered_index(k,len,self.rev)])
+    }
+
+    pub fn iter_mut(&mut self) -> Ordered<slice::IterMut<'_, T>> {
+        Ordered{inner:self.items.iter_mut(),rev:self.rev}
+    }
+
+    pub fn as_window(&self) -> Window<'_, T> {
+        Window{items:self.items,rev:self.rev}
+    }
+}
+
+impl<T> Index<usize> for Window<'_, T> {
+    type Output = T;
+    fn index(&self,k:usize) -> &T {
+        &self.items[ordered_index(k,self.len(),self.rev)]
+    }
+}
+
+impl<T> Index<usize> for WindowMut<'_, T> {
+    type Output = T;
+    fn index(&self,k:usize) -> &T {
+        &self.items[ordered_index(k,self.len(),self.rev)]
+    }
+}
+
+impl<T> IndexMut<usize> for WindowMut<'_, T> {
+    fn index_mut(&mut self,k:usize) -> &mut T {
+        let len = self.len();
+        &mut self.items[ordered_index(k,len,self.rev)]
+    }
+}
+
+impl<T:PartialEq> PartialEq<[T]> for Window<'_, T> {
+    fn eq(&self,other:&[T]) -> bool {
+        self.len() == other.len() && self.iter().eq(other)
+    }
+}
+
+impl<T:PartialEq, const N:usize> PartialEq<[T;N]> for Window<'_, T> {
+    fn eq(&self,other:&[T;N]) -> bool {
+        *self == other[..]
+    }
+}
+
+impl<T:fmt::Debug> fmt::Debug for Window<'_, T> {
+    fn fmt(&self,f:&mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T:fmt::Debug> fmt::Debug for WindowMut<'_, T> {
+    fn fmt(&self,f:&mut fmt::Formatter) -> fmt::Result {
+        self.as_window().fmt(f)
+    }
+}
+
+impl<'a, T> Stack<T> for StackRef<'a, T>{
+    type Part<'b> = StackRef<'b, T> where Self: 'b;
+
+    fn push(&mut self,v:T) -> Result<(),T> { self.push(v) }
+    fn pop(&mut self) -> Option<T> { self.pop() }
+    fn peek(&self) -> Option<&T> { self.peek() }
+    fn write_index(&self) -> usize { self.len }
+    fn room_left(&self) -> usize { self.room_left() }
+    unsafe fn set_write_index(&mut self,idx:usize){ unsafe { self.set_write_index(idx) } }
+
+    fn push_n<const SIZE:usize>(&mut self,v:[T;SIZE]) -> Result<(),[T;SIZE]> { self.push_n(v) }
+    fn push_slice(&mut self,v:&[T]) -> Result<(),()> where T: Clone { self.push_slice(v) }
+    fn pop_n<const SIZE:usize>(&mut self) -> Option<[T;SIZE]> { self.pop_n() }
+    fn pop_many<'b>(&'b mut self,size:usize) -> Option<Drain<'b, T>> { self.pop_many(size) }
+    fn peek_many<'b>(&'b self,size:usize) -> Option<Window<'b, T>> { self.peek_many(size).map(Window::new) }
+    fn drop_inside(&mut self,skip:usize,count:usize) -> Result<(),()> { self.drop_inside(skip,count) }
+    fn insert_inside(&mut self,skip:usize,v:&[T]) -> Result<(),()> where T: Clone { self.insert_inside(skip,v) }
+    fn extract_inside(&mut self,skip:usize,count:usize) -> Result<(),()> { self.extract_inside(skip,count) }
+    fn flush(&mut self,len:usize) { self.flush(len) }
+
+    fn split<'b>(&'b mut self) -> (WindowMut<'b, T>,StackRef<'b, T>) {
+        let (live,part) = self.split();
+        (WindowMut::new(live),part)
+    }
+}
+
+/// moves the elements above a drop_inside down over the dropped ones and lowers len,
+/// on drop so it also happens when one of the dropped values panics
+/// and the stack never gets to drop them a second time
+pub(crate) struct CloseGap<'l, T> {
+    pub(crate) src: *const T,
+    pub(crate) dst: *mut T,
+    pub(crate) count: usize,
+    pub(crate) len: &'l mut usize,
+    pub(crate) new_len: usize,
+}
+
+impl<T> Drop for CloseGap<'_, T> {
+    fn drop(&mut self){
+        unsafe { ptr::copy(self.src,self.dst,self.count) }
+        *self.len = self.new_len;
+    }
+}
+
+/// elements taken off a stack by pop_many
+/// yields them by value bottom first on every stack, next_back takes the top first,
+/// whatever is not taken is dropped with it
+pub struct Drain<'b, T> {
+    start: *mut T,
+    /// what is left lies at front..back of the buffer
+    front: usize,
+    back: usize,
+    /// the buffer holds them top first, as on a grow-down stack
+    rev: bool,
+
+    _phantom:PhantomData<&'b mut T>
+}
+
+impl<'b, T> Drain<'b, T> {
+    /// # Safety
+    /// the len values at start must be initialized and owned by nobody else
+    pub(crate) unsafe fn new(start:*mut T,len:usize) -> Self {
+        Self{start,front:0,back:len,rev:false,_phantom:PhantomData}
+    }
+
+    /// same as new for values lying top first in the buffer
+    ///
+    /// # Safety
+    /// same contract as new
+    pub(crate) unsafe fn new_rev(start:*mut T,len:usize) -> Self {
+        Self{start,front:0,back:len,rev:true,_phantom:PhantomData}
+    }
+
+    /// what is left in buffer order
+    fn rest(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.start.add(self.front),self.back-self.front) }
+    }
+
+    fn take_front(&mut self) -> T {
+        self.front += 1;
+        unsafe { self.start.add(self.front-1).read() }
+    }
+
+    fn take_back(&mut self) -> T {
+        self.back -= 1;
+        unsafe { self.start.add(self.back).read() }
+    }
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        match (self.front == self.back,self.rev) {
+            (true,_) => None,
+            (false,false) => Some(self.take_front()),
+            (false,true) => Some(self.take_back()),
+        }
+    }
+
+    fn size_hint(&self) -> (usize,Option<usize>) {
+        let len = self.back-self.front;
+        (len,Some(len))
+    }
+}
+
+impl<T> DoubleEndedIterator for Drain<'_, T> {
+    fn next_back(&mut self) -> Option<T> {
+        match (self.front == self.back,self.rev) {
+            (true,_) => None,
+            (false,false) => Some(self.take_back()),
+            (false,true) => Some(self.take_front()),
+        }
+    }
+}
+
+impl<T> ExactSizeIterator for Drain<'_, T> {}
+
+/// the stack gave these up before the drain was made so a panicking drop
+/// here cannot get them dropped again
+impl<T> Drop for Drain<'_, T> {
+    fn drop(&mut self){
+        unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.start.add(self.front),self.back-self.front)) }
+    }
+}
+
+impl<T:fmt::Debug> fmt::Debug for Drain<'_, T> {
+    fn fmt(&self,f:&mut fmt::Formatter) -> fmt::Result {
+        match self.rev {
+            false => f.debug_list().entries(self.rest()).finish(),
+            true => f.debug_list().entries(self.rest().iter().rev()).finish(),
+        }
+    }
+}
+
 #[test]
 fn test_lifo_order() {
     let mut storage = make_storage::<&'static str, 3>();
@@ -362,7 +872,6 @@ fn test_peek_n() {
     assert!(stack.peek_n::<4>().is_none());
 }
 
-use core::mem::size_of;
 
 #[test]
 fn test_peek_many() {
@@ -524,6 +1033,198 @@ fn test_split_stack() {
     assert_eq!(right.pop(), Some(10));
 }
 
+#[test]
+fn test_carve_and_rejoin() {
+    let mut storage = make_storage::<u32, 8>();
+    let mut stack = StackRef::from_slice(&mut storage);
+    stack.push(1).unwrap();
+
+    let mut a = stack.carve(3).unwrap();
+    let mut b = stack.carve(2).unwrap();
+    assert!(stack.carve(3).is_none());
+    assert_eq!(stack.room_left(), 2);
+
+    // all three can be filled without touching each other
+    stack.push_slice(&[2, 3]).unwrap();
+    assert!(stack.push(4).is_err());
+    a.push_slice(&[10, 11, 12]).unwrap();
+    assert!(a.push(13).is_err());
+    b.push_slice(&[20, 21]).unwrap();
+
+    assert_eq!(stack.peek_many(3), Some(&[1, 2, 3][..]));
+    assert_eq!(a.peek_many(3), Some(&[10, 11, 12][..]));
+    assert_eq!(b.pop(), Some(21));
+
+    // only the last carved part joins back
+    let a = stack.rejoin(a).unwrap_err();
+    assert!(stack.rejoin(b).is_ok());
+    assert!(stack.rejoin(a).is_ok());
+    assert_eq!(stack.room_left(), 5);
+}
+
+#[test]
+fn test_painting() {
+    let mut storage = make_storage::<u32, 8>();
+    let mut stack = StackRef::from_slice(&mut storage);
+    stack.push(1).unwrap();
+    stack.paint(0xdead);
+
+    stack.push_slice(&[2, 3, 4]).unwrap();
+    stack.flush(3);
+    assert_eq!(stack.write_index(), 1);
+    assert_eq!(unsafe { stack.painted_peak(&0xdead) }, 4);
+
+    // painting again forgets the old peak
+    stack.paint(0xdead);
+    assert_eq!(unsafe { stack.painted_peak(&0xdead) }, 1);
+}
+
+#[cfg(feature = "high-water")]
+#[test]
+fn test_high_water() {
+    let mut storage = make_storage::<u32, 8>();
+    let mut stack = StackRef::from_slice(&mut storage);
+
+    stack.push_n([1, 2, 3]).unwrap();
+    stack.flush(2);
+    assert_eq!(stack.peak(), 3);
+
+    // writes through split count even when only some are taken in
+    let (_, mut temp) = stack.split();
+    temp.push_slice(&[4, 5, 6, 7]).unwrap();
+    let (_, mut inner) = temp.split();
+    inner.push(8).unwrap();
+    core::mem::forget(inner);
+    core::mem::forget(temp);
+    unsafe { stack.advance(1) };
+    assert_eq!(stack.peak(), 6);
+    stack.flush(1);
+
+    stack.reset_peak();
+    assert_eq!(stack.peak(), 1);
+    stack.push(8).unwrap();
+    assert_eq!(stack.peak(), 2);
+}
+
+#[test]
+fn test_checked_write_index() {
+    let mut storage = make_storage::<u32, 6>();
+    let mut stack = StackRef::from_slice(&mut storage);
+
+    stack.push_slice(&[1, 2, 3]).unwrap();
+    stack.flush(3);
+    assert_eq!(stack.try_advance(4), Err(()));
+    assert_eq!(stack.try_advance(2), Ok(()));
+    assert_eq!(stack.peek_many(2), Some(&[1, 2][..]));
+    assert_eq!(stack.try_set_write_index(3), Ok(()));
+    assert_eq!(stack.try_set_write_index(0), Ok(()));
+
+    // building in place
+    stack.try_set_write_index(3).unwrap();
+    assert!(stack.push_uninit(4).is_none());
+    for (i, slot) in stack.push_uninit(2).unwrap().iter_mut().enumerate() {
+        slot.write(10 + i as u32);
+    }
+    unsafe { stack.commit(2) };
+    assert_eq!(stack.peek_many(3), Some(&[3, 10, 11][..]));
+    assert_eq!(stack.try_advance(1), Err(()));
+    assert_eq!(stack.try_advance(usize::MAX), Err(()));
+
+    // only written slots of a carved part count
+    stack.flush(5);
+    let _ = stack.carve(3).unwrap();
+    assert_eq!(stack.try_set_write_index(3), Ok(()));
+    assert_eq!(stack.try_set_write_index(4), Err(()));
+}
+
+/// counts its drops, clones are counted too
+#[cfg(test)]
+#[derive(Clone)]
+struct Counted<'c>(u32, &'c core::cell::Cell<usize>);
+
+#[cfg(test)]
+impl Drop for Counted<'_> {
+    fn drop(&mut self) {
+        self.1.set(self.1.get() + 1);
+    }
+}
+
+#[test]
+fn test_drops_exactly_once() {
+    let drops = core::cell::Cell::new(0);
+    let mut storage = make_storage::<Counted, 8>();
+    let mut stack = StackRef::from_slice(&mut storage);
+    for i in 0..6 {
+        assert!(stack.push(Counted(i, &drops)).is_ok());
+    }
+
+    stack.flush(1);
+    assert_eq!(drops.get(), 1);
+
+    // removes 1 and 2
+    stack.drop_inside(3, 2).unwrap();
+    assert_eq!(drops.get(), 3);
+    let ids = [0, 3, 4].map(|i| stack.peek_many(3).unwrap().iter().any(|c| c.0 == i));
+    assert_eq!(ids, [true; 3]);
+
+    let mut drain = stack.pop_many(2).unwrap();
+    let first = drain.next().unwrap();
+    assert_eq!((first.0, drain.len()), (3, 1));
+    drop(first);
+    assert_eq!(drops.get(), 4);
+    drop(drain);
+    assert_eq!(drops.get(), 5);
+
+    stack.push_slice(&[Counted(7, &drops), Counted(8, &drops)]).unwrap();
+    // the two temporaries above
+    assert_eq!(drops.get(), 7);
+    drop(stack);
+    assert_eq!(drops.get(), 10);
+}
+
+/// counts its drops, the first armed one to be dropped panics
+#[cfg(test)]
+struct Bomb<'c>(bool, &'c core::cell::Cell<usize>, &'c core::cell::Cell<bool>);
+
+#[cfg(test)]
+impl Drop for Bomb<'_> {
+    fn drop(&mut self) {
+        self.1.set(self.1.get() + 1);
+        if self.0 && self.2.replace(false) {
+            panic!("bomb");
+        }
+    }
+}
+
+#[test]
+fn test_panicking_drop_drops_once() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let drops = core::cell::Cell::new(0);
+    let fuse = core::cell::Cell::new(true);
+    let mut storage = make_storage::<Bomb, 8>();
+    let mut stack = StackRef::from_slice(&mut storage);
+    for i in 0..5 {
+        assert!(stack.push(Bomb(i == 2, &drops, &fuse)).is_ok());
+    }
+
+    // removes 1 and 2, dropping 2 panics
+    assert!(catch_unwind(AssertUnwindSafe(|| stack.drop_inside(3, 2))).is_err());
+    assert_eq!((drops.get(), stack.write_index()), (2, 3));
+    assert_eq!(stack.peek_many(3).unwrap().iter().map(|b| b.0).collect::<std::vec::Vec<_>>(), [false; 3]);
+
+    // a drain that panics part way still drops the rest, once
+    for armed in [true, false] {
+        assert!(stack.push(Bomb(armed, &drops, &fuse)).is_ok());
+    }
+    fuse.set(true);
+    let drain = stack.pop_many(3).unwrap();
+    assert!(catch_unwind(AssertUnwindSafe(|| drop(drain))).is_err());
+    assert_eq!((drops.get(), stack.write_index()), (5, 2));
+    drop(stack);
+    assert_eq!(drops.get(), 7);
+}
+
 #[test]
 fn test_push_slice_success_and_error() {
     let mut storage = make_storage::<u32, 5>();
@@ -608,3 +1309,239 @@ fn test_full_usage() {
     stack.pop_many(4).ok_or(()).unwrap_err();
     stack.pop_many(2).unwrap();
 }
+
+#[test]
+fn test_empty_and_zero_sized() {
+    let mut storage = make_storage::<u32, 2>();
+    let mut stack = StackRef::from_slice(&mut storage);
+    assert_eq!(stack.push_slice(&[]), Ok(()));
+    assert_eq!(stack.push_n([]), Ok(()));
+    assert_eq!(stack.pop_n::<0>(), Some([]));
+    assert_eq!(stack.write_index(), 0);
+
+    let mut storage = make_storage::<(), 3>();
+    let mut stack = StackRef::from_slice(&mut storage);
+    stack.push_n([(), ()]).unwrap();
+    assert_eq!(stack.room_left(), 1);
+    let mut part = stack.carve(1).unwrap();
+    assert!(stack.push(()).is_err());
+    part.push(()).unwrap();
+    assert!(stack.rejoin(part).is_ok());
+    assert_eq!(stack.peek_many(2), Some(&[(), ()][..]));
+    assert_eq!(stack.pop_many(3).map(|d| d.len()), None);
+    assert_eq!(stack.pop_many(2).map(|d| d.len()), Some(2));
+    assert!(stack.pop().is_none());
+}
+
+/// the operations the model test runs, counts are kept small so they often hit the edges
+#[cfg(test)]
+#[derive(Debug, Clone)]
+enum Op {
+    Push(u32),
+    PushN([u32; 3]),
+    PushSlice(std::vec::Vec<u32>),
+    Pop,
+    PopN,
+    PopMany(usize),
+    PeekN,
+    PeekMany(usize),
+    DropInside(usize, usize),
+    InsertInside(usize, std::vec::Vec<u32>),
+    ExtractInside(usize, usize),
+    Flush(usize),
+}
+
+#[cfg(test)]
+fn op_strategy() -> impl proptest::strategy::Strategy<Value = Op> {
+    use proptest::prelude::*;
+    //past ten arms prop_oneof builds a vec
+    use std::vec;
+    prop_oneof![
+        any::<u32>().prop_map(Op::Push),
+        any::<[u32; 3]>().prop_map(Op::PushN),
+        proptest::collection::vec(any::<u32>(), 0..6).prop_map(Op::PushSlice),
+        Just(Op::Pop),
+        Just(Op::PopN),
+        (0..8usize).prop_map(Op::PopMany),
+        Just(Op::PeekN),
+        (0..8usize).prop_map(Op::PeekMany),
+        (0..8usize, 0..8usize).prop_map(|(s, c)| Op::DropInside(s, c)),
+        (0..8usize, proptest::collection::vec(any::<u32>(), 0..4)).prop_map(|(s, v)| Op::InsertInside(s, v)),
+        (0..8usize, 0..8usize).prop_map(|(s, c)| Op::ExtractInside(s, c)),
+        (0..4usize).prop_map(Op::Flush),
+    ]
+}
+
+/// runs ops against a stack of capacity cap and a Vec, wrap turns the numbers into elements
+#[cfg(test)]
+fn check_against_vec<T, F>(ops: &[Op], cap: usize, wrap: F)
+where T: Clone + PartialEq + fmt::Debug, F: Fn(u32) -> T {
+    use std::vec::Vec;
+
+    let mut storage: Vec<MaybeUninit<T>> = (0..cap).map(|_| MaybeUninit::uninit()).collect();
+    let mut stack = StackRef::from_slice(&mut storage);
+    let mut model: Vec<T> = Vec::new();
+
+    for op in ops {
+        let len = model.len();
+        match op {
+            Op::Push(x) => {
+                let r = stack.push(wrap(*x)).is_ok();
+                assert_eq!(r, len < cap);
+                if r { model.push(wrap(*x)) }
+            }
+            Op::PushN(xs) => {
+                let r = stack.push_n(xs.map(&wrap)).is_ok();
+                assert_eq!(r, len + 3 <= cap);
+                if r { model.extend(xs.map(&wrap)) }
+            }
+            Op::PushSlice(xs) => {
+                let xs: Vec<T> = xs.iter().map(|x| wrap(*x)).collect();
+                let r = stack.push_slice(&xs).is_ok();
+                assert_eq!(r, len + xs.len() <= cap);
+                if r { model.extend(xs) }
+            }
+            Op::Pop => assert_eq!(stack.pop(), model.pop()),
+            Op::PopN => {
+                let got = stack.pop_n::<2>();
+                assert_eq!(got.is_some(), len >= 2);
+                if let Some(got) = got {
+                    assert_eq!(&got[..], &model[len - 2..]);
+                    model.truncate(len - 2);
+                }
+            }
+            Op::PopMany(n) => {
+                let got: Option<Vec<T>> = stack.pop_many(*n).map(|d| d.collect());
+                assert_eq!(got.is_some(), *n <= len);
+                if let Some(got) = got {
+                    assert_eq!(got, model.split_off(len - n));
+                }
+            }
+            Op::PeekN => assert_eq!(stack.peek_n::<2>().map(|a| &a[..]), model.get(len.wrapping_sub(2)..)),
+            Op::PeekMany(n) => assert_eq!(stack.peek_many(*n), model.get(len.wrapping_sub(*n)..)),
+            Op::DropInside(skip, count) => {
+                let r = stack.drop_inside(*skip, *count).is_ok();
+                let fits = *skip == 0 || (*skip < len && len - 1 - skip + count < len);
+                assert_eq!(r, fits);
+                if r && *skip != 0 {
+                    let spot = len - 1 - skip;
+                    model.drain(spot..spot + count);
+                }
+            }
+            Op::InsertInside(skip, xs) => {
+                let xs: Vec<T> = xs.iter().map(|x| wrap(*x)).collect();
+                let r = stack.insert_inside(*skip, &xs).is_ok();
+                assert_eq!(r, *skip <= len && len + xs.len() <= cap);
+                if r {
+                    let at = len - skip;
+                    model.splice(at..at, xs);
+                }
+            }
+            Op::ExtractInside(skip, count) => {
+                let r = stack.extract_inside(*skip, *count).is_ok();
+                assert_eq!(r, skip + count <= len);
+                if r {
+                    let at = len - skip - count;
+                    let moved: Vec<T> = model.drain(at..at + count).collect();
+                    model.extend(moved);
+                }
+            }
+            Op::Flush(n) => {
+                stack.flush(*n);
+                model.truncate(len.saturating_sub(*n));
+            }
+        }
+        assert_eq!(stack.write_index(), model.len());
+        assert_eq!(stack.room_left(), cap - model.len());
+        assert_eq!(stack.peek_many(model.len()), Some(&model[..]));
+    }
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    // miri has no file system for the failure log and runs a few cases at most
+    #![proptest_config(proptest::test_runner::Config {
+        cases: if cfg!(miri) { 4 } else { proptest::test_runner::Config::default().cases },
+        failure_persistence: if cfg!(miri) { None } else { proptest::test_runner::Config::default().failure_persistence },
+        ..proptest::test_runner::Config::default()
+    })]
+    #[test]
+    fn test_matches_vec_model(ops in proptest::collection::vec(op_strategy(), 0..64), cap in 0..12usize) {
+        check_against_vec(&ops, cap, |x| x);
+    }
+
+    #[test]
+    fn test_matches_vec_model_zero_sized(ops in proptest::collection::vec(op_strategy(), 0..64), cap in 0..12usize) {
+        check_against_vec(&ops, cap, |_| ());
+    }
+
+    #[test]
+    fn test_matches_vec_model_owned(ops in proptest::collection::vec(op_strategy(), 0..64), cap in 0..12usize) {
+        check_against_vec(&ops, cap, std::boxed::Box::new);
+    }
+}
+
+/// the same calls through the trait give the same answers on both stacks
+#[cfg(test)]
+fn exercise<S: Stack<u32>>(stack: &mut S) {
+    for i in 1..=5 {
+        stack.push(i).unwrap();
+    }
+    assert!(stack.push(6).is_err());
+
+    // drops 3 and 2
+    stack.drop_inside(3, 2).unwrap();
+    assert_eq!(stack.drop_inside(3, 1), Err(()));
+    assert_eq!(stack.write_index(), 3);
+
+    stack.flush(1);
+    let (live, mut part) = stack.split();
+    assert_eq!((live.len(), live[0], live[1]), (2, 1, 4));
+    part.push(7).unwrap();
+    part.push(8).unwrap();
+    core::mem::forget(part);
+    unsafe { stack.advance(2) };
+
+    let mut popped = [0; 2];
+    for p in popped.iter_mut() {
+        *p = stack.pop().unwrap();
+    }
+    assert_eq!(popped, [8, 7]);
+
+    // drained bottom first whichever way the buffer grows
+    let mut drain = stack.pop_many(2).unwrap();
+    assert_eq!((drain.next(), drain.next_back(), drain.next()), (Some(1), Some(4), None));
+    drop(drain);
+    assert!(stack.is_empty());
+
+    // runs go in and come out with the top last
+    stack.push_slice(&[1, 2]).unwrap();
+    assert_eq!(stack.peek(), Some(&2));
+    stack.push_n([3, 4]).unwrap();
+    assert_eq!(stack.peek(), Some(&4));
+    assert_eq!(stack.peek_many(4).unwrap(), [1, 2, 3, 4]);
+    assert_eq!(stack.peek_n::<2>().unwrap(), [3, 4]);
+    assert_eq!(stack.pop_n::<2>(), Some([3, 4]));
+
+    // slid under the top one still in order
+    stack.insert_inside(1, &[5, 6]).unwrap();
+    assert_eq!(stack.peek_many(4).unwrap(), [1, 5, 6, 2]);
+    assert!(stack.insert_inside(5, &[0]).is_err());
+    stack.extract_inside(1, 2).unwrap();
+    assert_eq!(stack.peek_many(4).unwrap(), [1, 2, 5, 6]);
+
+    let (mut live, _) = stack.split();
+    live[3] = 9;
+    assert_eq!(live.iter_mut().map(|v| *v).collect::<std::vec::Vec<_>>(), [1, 2, 5, 9]);
+    assert_eq!(stack.peek(), Some(&9));
+    stack.flush_all();
+}
+
+#[test]
+fn test_stacks_agree() {
+    let mut storage = make_storage::<u32, 5>();
+    exercise(&mut StackRef::from_slice(&mut storage));
+    let mut storage = make_storage::<u32, 5>();
+    exercise(&mut crate::rev_stack::RevStackRef::from_slice(&mut storage));
+    exercise(&mut crate::array_stack::ArrayStack::<u32, 5>::new());
+}
@@ -0,0 +1,94 @@
+//! tokens with a fixed meaning
+//! a symbol table has to hand these ids out before any user symbol
+
+use crate::stack::StackRef;
+use crate::value::Error;
+
+pub const QUOTE: u16 = 0;
+pub const QUASIQUOTE: u16 = 1;
+pub const UNQUOTE: u16 = 2;
+pub const UNQUOTE_SPLICING: u16 = 3;
+/// `...` in syntax-rules
+pub const ELLIPSIS: u16 = 4;
+/// `_` in syntax-rules
+pub const UNDERSCORE: u16 = 5;
+pub const DEFMACRO: u16 = 6;
+
+/// first id free for user symbols
+pub const FIRST_FREE: u16 = 7;
+
+/// names of the fixed symbols in id order
+const FIXED: [&str; FIRST_FREE as usize] = ["quote", "quasiquote", "unquote", "unquote-splicing", "...", "_", "defmacro"];
+
+/// maps symbol names to token ids, backed by a fixed buffer of names
+pub struct SymbolTable<'a> {
+	names: StackRef<'a, &'a str>,
+}
+
+impl<'a> SymbolTable<'a> {
+	/// the fixed symbols are entered first so their ids match the constants above
+	pub fn new(mem: impl Into<StackRef<'a, &'a str>>) -> Result<Self, Error> {
+		let mut names = mem.into();
+		names.push_slice(&FIXED).map_err(|_| Error::CapacityError)?;
+		Ok(Self { names })
+	}
+
+	pub fn len(&self) -> usize {
+		self.names.write_index()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn find(&self, name: &str) -> Option<u16> {
+		let all = self.names.peek_many(self.len())?;
+		all.iter().position(|n| *n == name).map(|i| i as u16)
+	}
+
+	pub fn name(&self, id: u16) -> Option<&'a str> {
+		let all = self.names.peek_many(self.len())?;
+		all.get(id as usize).copied()
+	}
+
+	/// returns the id of name, adding it if it is new
+	pub fn intern(&mut self, name: &'a str) -> Result<u16, Error> {
+		if let Some(id) = self.find(name) {
+			return Ok(id)
+		}
+
+		let id = u16::try_from(self.len()).map_err(|_| Error::CapacityError)?;
+		self.names.push(name).map_err(|_| Error::CapacityError)?;
+		Ok(id)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::stack::make_storage;
+
+	#[test]
+	fn interning() {
+		let mut storage = make_storage::<_, 9>();
+		let mut table = SymbolTable::new(&mut storage).unwrap();
+
+		assert_eq!(table.find("unquote-splicing"), Some(UNQUOTE_SPLICING));
+		assert_eq!(table.name(ELLIPSIS), Some("..."));
+
+		let x = table.intern("x").unwrap();
+		assert_eq!(x, FIRST_FREE);
+		assert_eq!(table.intern("y").unwrap(), FIRST_FREE + 1);
+		assert_eq!(table.intern("x").unwrap(), x);
+		assert_eq!(table.name(x), Some("x"));
+
+		assert_eq!(table.intern("z"), Err(Error::CapacityError));
+		assert_eq!(table.find("z"), None);
+	}
+
+	#[test]
+	fn too_small_for_fixed() {
+		let mut storage = make_storage::<_, 3>();
+		assert!(SymbolTable::new(&mut storage).is_err());
+	}
+}
@@ -0,0 +1,384 @@
+use crate::value::{objects, Error, ValueStack, ValueTag};
+
+/*
+ * a table is one object whose capacity is fixed when it is made
+ *
+ * [k0 v0 k1 v1 .. | index(cap) | Int(cap) Int(count) Table(slots)]
+ *
+ * entries are packed at the bottom in insertion order
+ * each value can be any object while keys are single slots
+ *
+ * the index sits right under the header so bucket i is found in O(1)
+ * it holds Nil for an empty bucket or Int(p) where p is the offset
+ * from the start of the table to the header of the value
+ * collisions probe linearly, deletions and resizes just rebuild it
+ */
+
+struct Layout {
+	/// index of the first slot of the table
+	start: usize,
+	/// slots taken by the entries
+	entries: usize,
+	cap: usize,
+	count: usize,
+}
+
+impl Layout {
+	fn index(&self) -> usize { self.start+self.entries }
+	fn end(&self) -> usize { self.index()+self.cap+3 }
+}
+
+enum Probe {
+	/// the value header of the matching entry
+	Found(usize),
+	Vacant(usize),
+	Full,
+}
+
+fn hash(key:ValueTag) -> Result<u64,Error>{
+	let (kind,bits) = match key {
+		ValueTag::Int(i) => (1,i as u64),
+		ValueTag::Token(t) => (2,t as u64),
+		ValueTag::Char(c) => (3,c as u64),
+		_ => return Err(Error::TypeError),
+	};
+
+	let x = (bits ^ (kind << 56)).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+	Ok(x ^ (x >> 29))
+}
+
+fn table_at(room:&[ValueTag],top:usize) -> Result<Layout,Error>{
+	let slots = match room.get(..top).and_then(|r| r.last()) {
+		None => return Err(Error::StackUnderflow),
+		Some(ValueTag::Table(u)) => *u,
+		Some(_) => return Err(Error::TypeError),
+	};
+
+	let start = top.checked_sub(slots+1).ok_or(Error::TypeError)?;
+	match room[start..top] {
+		[.., ValueTag::Int(cap), ValueTag::Int(count), _] => {
+			let cap = usize::try_from(cap).map_err(|_| Error::TypeError)?;
+			let count = usize::try_from(count).map_err(|_| Error::TypeError)?;
+			let entries = slots.checked_sub(cap+2).ok_or(Error::TypeError)?;
+			Ok(Layout{start,entries,cap,count})
+		}
+		_ => Err(Error::TypeError),
+	}
+}
+
+fn probe(room:&[ValueTag],t:&Layout,key:ValueTag) -> Result<Probe,Error>{
+	let first = (hash(key)? % t.cap as u64) as usize;
+	let mut vacant = None;
+
+	for i in 0..t.cap {
+		let bucket = (first+i) % t.cap;
+		match room[t.index()+bucket] {
+			ValueTag::Int(p) => {
+				let value = t.start + p as usize;
+				let k = value - room[value].get_size();
+				if room[k] == key {
+					return Ok(Probe::Found(value))
+				}
+			}
+			_ => {
+				vacant = Some(bucket);
+				break;
+			}
+		}
+	}
+
+	Ok(vacant.map_or(Probe::Full,Probe::Vacant))
+}
+
+/// clears the index and fills it again from the entries
+fn rebuild(room:&mut [ValueTag],t:&Layout){
+	let (entries,index) = room[t.start..].split_at_mut(t.entries);
+	let index = &mut index[..t.cap];
+	index.fill(ValueTag::Nil);
+
+	let mut end = entries.len();
+	let mut it = objects(entries);
+	while let (Some(value),Some(key)) = (it.next(),it.next()) {
+		let mut bucket = (hash(key[0]).unwrap_or(0) % t.cap as u64) as usize;
+		while index[bucket] != ValueTag::Nil {
+			bucket = (bucket+1) % t.cap;
+		}
+		index[bucket] = ValueTag::Int((end-1) as i64);
+		end -= value.len()+1;
+	}
+}
+
+/// rebuilds the index of the table ending at top after its entries were changed in place
+pub(crate) fn reindex(room:&mut [ValueTag],top:usize) -> Result<(),Error>{
+	let t = table_at(room,top)?;
+	rebuild(room,&t);
+	Ok(())
+}
+
+fn write_header(room:&mut [ValueTag],t:&Layout){
+	let i = t.index()+t.cap;
+	room[i] = ValueTag::Int(t.cap as i64);
+	room[i+1] = ValueTag::Int(t.count as i64);
+	room[i+2] = ValueTag::Table(t.entries+t.cap+2);
+}
+
+/// iterates (key,value) pairs of a table object from the newest entry down
+pub fn entries(table:&[ValueTag]) -> Result<impl Iterator<Item=(ValueTag,&[ValueTag])>,Error>{
+	let t = table_at(table,table.len())?;
+	let mut it = objects(&table[t.start..t.index()]);
+	Ok(core::iter::from_fn(move || {
+		let value = it.next()?;
+		let key = it.next()?;
+		Some((key[0],value))
+	}))
+}
+
+/// (make-table cap)
+pub fn make_table(stack:&mut ValueStack) -> Result<(),Error>{
+	let cap = match stack.peek() {
+		None => return Err(Error::StackUnderflow),
+		Some(ValueTag::Int(c)) if *c > 0 => *c as usize,
+		Some(ValueTag::Int(_)) => return Err(Error::OutOfRange),
+		Some(_) => return Err(Error::TypeError),
+	};
+	if cap.checked_add(2).is_none_or(|n| n > stack.room_left()) {
+		return Err(Error::StackOverflow)
+	}
+
+	stack.pop();
+	for _ in 0..cap {
+		stack.push(ValueTag::Nil).map_err(|_| Error::StackOverflow)?;
+	}
+	stack.push_n([ValueTag::Int(cap as i64),ValueTag::Int(0),ValueTag::Table(cap+2)])
+		.map_err(|_| Error::StackOverflow)
+}
+
+/// (table-count t)
+pub fn table_count(stack:&mut ValueStack) -> Result<(),Error>{
+	let top = stack.write_index();
+	let (room,_) = stack.split();
+	let t = table_at(room,top)?;
+
+	stack.flush(top-t.start);
+	stack.push(ValueTag::Int(t.count as i64)).map_err(|_| Error::StackOverflow)
+}
+
+/// (table-ref t key default)
+/// stack: [table key default] -> [value or default]
+pub fn table_ref(stack:&mut ValueStack) -> Result<(),Error>{
+	let default_size = stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+	let top = stack.write_index();
+	let key_at = top.checked_sub(default_size+1).ok_or(Error::StackUnderflow)?;
+
+	let (room,_) = stack.split();
+	let t = table_at(room,key_at)?;
+	let (from,size) = match probe(room,&t,room[key_at])? {
+		Probe::Found(value) => {
+			let size = room[value].get_size();
+			(value+1-size,size)
+		}
+		_ => (top-default_size,default_size),
+	};
+
+	room.copy_within(from..from+size, t.start);
+	stack.flush(top-t.start-size);
+	Ok(())
+}
+
+/// (table-set! t key value)
+/// stack: [table key value] -> [table]
+/// fails with CapacityError instead of growing when a new key does not fit
+pub fn table_set(stack:&mut ValueStack) -> Result<(),Error>{
+	let new_size = stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+	let top = stack.write_index();
+	let key_at = top.checked_sub(new_size+1).ok_or(Error::StackUnderflow)?;
+
+	let (room,_) = stack.split();
+	let mut t = table_at(room,key_at)?;
+	let key = room[key_at];
+
+	let new_top = match probe(room,&t,key)? {
+		Probe::Full => return Err(Error::CapacityError),
+		Probe::Vacant(bucket) => {
+			//[index info key value] -> [key value index info]
+			room[t.index()..top].rotate_right(new_size+1);
+			t.entries += new_size+1;
+			t.count += 1;
+			room[t.index()+bucket] = ValueTag::Int((t.entries-1) as i64);
+			write_header(room,&t);
+			t.end()
+		}
+		Probe::Found(value) => {
+			let old_size = room[value].get_size();
+			let at = value+1-old_size;
+
+			//[old rest key new] -> [new old rest key] -> [new rest]
+			room[at..top].rotate_right(new_size);
+			let rest = key_at-value-1;
+			let from = at+new_size+old_size;
+			room.copy_within(from..from+rest, at+new_size);
+
+			t.entries = t.entries+new_size-old_size;
+			write_header(room,&t);
+			if new_size != old_size {
+				rebuild(room,&t);
+			}
+			t.end()
+		}
+	};
+
+	stack.flush(top-new_top);
+	Ok(())
+}
+
+/// (table-delete! t key)
+/// stack: [table key] -> [table]
+pub fn table_delete(stack:&mut ValueStack) -> Result<(),Error>{
+	let top = stack.write_index();
+	let key_at = top.checked_sub(1).ok_or(Error::StackUnderflow)?;
+
+	let (room,_) = stack.split();
+	let mut t = table_at(room,key_at)?;
+
+	if let Probe::Found(value) = probe(room,&t,room[key_at])? {
+		let at = value-room[value].get_size();
+		let removed = value+1-at;
+		room.copy_within(value+1..key_at, at);
+
+		t.entries -= removed;
+		t.count -= 1;
+		write_header(room,&t);
+		rebuild(room,&t);
+	}
+
+	stack.flush(top-t.end());
+	Ok(())
+}
+
+/// (table->list t)
+/// turns the table into a flat (k0 v0 k1 v1 ..) list in insertion order
+pub fn table_to_list(stack:&mut ValueStack) -> Result<(),Error>{
+	let top = stack.write_index();
+	let (room,_) = stack.split();
+	let t = table_at(room,top)?;
+
+	stack.flush(top-t.index());
+	stack.push(ValueTag::Cons(t.entries)).map_err(|_| Error::StackOverflow)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ValueTag::*;
+	use crate::array_stack::ArrayStack;
+
+	fn lookup(stack:&mut ValueStack,key:ValueTag) -> ValueTag {
+		let top = stack.write_index();
+		let (room,_) = stack.split();
+		let t = table_at(room,top).unwrap();
+		dup(stack,t.start,top-t.start);
+		stack.push_slice(&[key,Bool(false)]).unwrap();
+		table_ref(stack).unwrap();
+
+		let found = stack.pop().unwrap();
+		stack.flush(found.get_size()-1);
+		found
+	}
+
+	fn dup(stack:&mut ValueStack,start:usize,len:usize){
+		crate::value::dup_range(stack,start,len).unwrap();
+	}
+
+	fn set(stack:&mut ValueStack,kv:&[ValueTag]) -> Result<(),Error>{
+		stack.push_slice(kv).unwrap();
+		table_set(stack)
+	}
+
+	#[test]
+	fn set_ref_and_count() {
+		let mut stack = ArrayStack::<_,64>::new();
+
+		stack.push(Int(4)).unwrap();
+		make_table(&mut stack.view()).unwrap();
+		assert_eq!(stack.len(),7);
+
+		set(&mut stack.view(),&[Int(1),Char('a')]).unwrap();
+		set(&mut stack.view(),&[Token(1),Int(2),Int(3),Cons(2)]).unwrap();
+		set(&mut stack.view(),&[Char('z'),Nil]).unwrap();
+
+		assert_eq!(lookup(&mut stack.view(),Int(1)),Char('a'));
+		assert_eq!(lookup(&mut stack.view(),Char('z')),Nil);
+		assert_eq!(lookup(&mut stack.view(),Int(7)),Bool(false));
+		assert_eq!(lookup(&mut stack.view(),Token(1)),Cons(2));
+
+		{
+			let table = stack.peek_many(stack.len()).unwrap();
+			let mut seen = entries(table).unwrap();
+			assert_eq!(seen.next(),Some((Char('z'),&[Nil][..])));
+			assert_eq!(seen.next(),Some((Token(1),&[Int(2),Int(3),Cons(2)][..])));
+			assert_eq!(seen.next(),Some((Int(1),&[Char('a')][..])));
+			assert_eq!(seen.next(),None);
+		}
+
+		table_count(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(),Some(Int(3)));
+		assert_eq!(stack.pop(),None);
+	}
+
+	#[test]
+	fn replace_with_other_sizes() {
+		let mut stack = ArrayStack::<_,64>::new();
+
+		stack.push(Int(3)).unwrap();
+		make_table(&mut stack.view()).unwrap();
+		set(&mut stack.view(),&[Int(1),Int(10)]).unwrap();
+		set(&mut stack.view(),&[Int(2),Int(20)]).unwrap();
+
+		set(&mut stack.view(),&[Int(1),Int(5),Int(6),Cons(2)]).unwrap();
+		assert_eq!(lookup(&mut stack.view(),Int(2)),Int(20));
+		assert_eq!(lookup(&mut stack.view(),Int(1)),Cons(2));
+
+		set(&mut stack.view(),&[Int(1),Int(11)]).unwrap();
+		set(&mut stack.view(),&[Int(2),Int(22)]).unwrap();
+		assert_eq!(lookup(&mut stack.view(),Int(1)),Int(11));
+		assert_eq!(lookup(&mut stack.view(),Int(2)),Int(22));
+
+		table_to_list(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(5).unwrap(),&[Int(1),Int(11),Int(2),Int(22),Cons(4)]);
+		assert_eq!(stack.len(),5);
+	}
+
+	#[test]
+	fn capacity_and_delete() {
+		let mut stack = ArrayStack::<_,64>::new();
+
+		stack.push(Int(2)).unwrap();
+		make_table(&mut stack.view()).unwrap();
+		set(&mut stack.view(),&[Int(1),Int(10)]).unwrap();
+		set(&mut stack.view(),&[Int(2),Int(20)]).unwrap();
+
+		let before = stack.len();
+		assert!(matches!(set(&mut stack.view(),&[Int(3),Int(30)]),Err(Error::CapacityError)));
+		stack.flush(2);
+		assert_eq!(stack.len(),before);
+
+		//existing keys can still be replaced when full
+		set(&mut stack.view(),&[Int(2),Int(21)]).unwrap();
+
+		stack.push(Int(1)).unwrap();
+		table_delete(&mut stack.view()).unwrap();
+		assert_eq!(lookup(&mut stack.view(),Int(1)),Bool(false));
+		assert_eq!(lookup(&mut stack.view(),Int(2)),Int(21));
+
+		set(&mut stack.view(),&[Int(3),Int(30)]).unwrap();
+		assert_eq!(lookup(&mut stack.view(),Int(3)),Int(30));
+
+		//deleting a missing key leaves the table alone
+		stack.push(Int(9)).unwrap();
+		table_delete(&mut stack.view()).unwrap();
+		assert_eq!(stack.len(),before);
+
+		stack.push_slice(&[Float(1.0),Nil]).unwrap();
+		assert!(matches!(table_set(&mut stack.view()),Err(Error::TypeError)));
+	}
+}
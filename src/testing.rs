@@ -0,0 +1,82 @@
+//! helpers shared by the unit tests
+
+use core::fmt;
+use crate::eval::Op;
+use crate::value::{Error, ValueStack, ValueTag::*};
+
+/// collects formatted text in a fixed buffer, writing past the end fails
+pub struct Buf<const N:usize> {
+	data: [u8;N],
+	len: usize,
+}
+
+impl<const N:usize> Buf<N> {
+	pub fn new() -> Self {
+		Self{data:[0;N],len:0}
+	}
+
+	pub fn as_str(&self) -> &str {
+		core::str::from_utf8(&self.data[..self.len]).unwrap()
+	}
+}
+
+impl<const N:usize> fmt::Write for Buf<N> {
+	fn write_str(&mut self,s:&str) -> fmt::Result {
+		let end = self.len+s.len();
+		self.data.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+		self.len = end;
+		Ok(())
+	}
+}
+
+fn int2(stack:&mut ValueStack) -> Result<(i64,i64),Error>{
+	match stack.peek_n::<2>() {
+		Some([Int(a),Int(b)]) => Ok((*a,*b)),
+		Some(_) => Err(Error::TypeError),
+		None => Err(Error::StackUnderflow),
+	}
+}
+
+pub fn add(stack:&mut ValueStack) -> Result<(),Error>{
+	let (a,b) = int2(stack)?;
+	stack.flush(2);
+	stack.push(Int(a+b)).map_err(|_| Error::StackOverflow)
+}
+
+pub fn sub(stack:&mut ValueStack) -> Result<(),Error>{
+	let (a,b) = int2(stack)?;
+	stack.flush(2);
+	stack.push(Int(a-b)).map_err(|_| Error::StackOverflow)
+}
+
+pub fn positive(stack:&mut ValueStack) -> Result<(),Error>{
+	match stack.peek() {
+		Some(Int(a)) => {
+			let ans = *a > 0;
+			stack.pop();
+			stack.push(Bool(ans)).map_err(|_| Error::StackOverflow)
+		}
+		_ => Err(Error::TypeError),
+	}
+}
+
+/// sums n..=1 with n pushed by the caller, leaves the sum
+/// stack during the loop is [n acc]
+pub const SUM: [Op;14] = [
+	Op::Push(Int(0)),
+	//loop: 1
+	Op::Pick(1),
+	Op::Prim(positive),
+	Op::JumpIfFalse(11),
+	Op::Pick(1),
+	Op::Prim(add),
+	Op::Swap,
+	Op::Push(Int(1)),
+	Op::Prim(sub),
+	Op::Swap,
+	Op::Jump(1),
+	//done: 11
+	Op::Swap,
+	Op::Drop,
+	Op::Ret,
+];
@@ -1,7 +1,4 @@
-use crate::stack::take_last_raw;
-use core::ptr;
-use crate::stack::take_last;
-use crate::stack::take_last_mut;
+use core::mem;
 use crate::stack::StackRef;
 
 
@@ -14,9 +11,29 @@ pub enum ValueTag {
 	Bool(bool),
 	Token(u16),
 	Code(u64),
+	Char(char),
+	/// a coroutine owned by the running machine, see machine.rs
+	Coroutine(u16),
+	/// an escape continuation: value stack index of its own slot,
+	/// control depth and return address when it was captured
+	/// and which capture it was, so a later one in the same spot is told apart
+	Escape(u32,u32,u32,u16),
+	/// a host function, see native.rs
+	Native(u16),
+	/// a host resource as kind and index into the host's table, see handle.rs
+	Handle(u16,u32),
+	/// an object on the value stack that does not keep it alive,
+	/// as slot in the weak table and the generation of that slot, see gc.rs
+	Weak(u16,u32),
 
 	Cons(usize),
 	Func(usize),
+	/// elements followed by an Int holding their count
+	Vector(usize),
+	/// fixed capacity hash table, see table.rs for the layout
+	Table(usize),
+	/// slots nobody reads any more, left in place for the collector, see gc.rs
+	Junk(usize),
 }
 
 impl ValueTag{
@@ -25,64 +42,163 @@ impl ValueTag{
 			ValueTag::Int(_) |
 			ValueTag::Float(_) |
 			ValueTag::Token(_) | ValueTag::Code(_) |
-			ValueTag::Nil | ValueTag::Bool(_) 
+			ValueTag::Nil | ValueTag::Bool(_) |
+			ValueTag::Char(_) | ValueTag::Coroutine(_) |
+			ValueTag::Escape(..) | ValueTag::Native(_) |
+			ValueTag::Handle(..) | ValueTag::Weak(..)
 			=> {1},
 			
-			ValueTag::Cons(u) | ValueTag::Func(u) => u+1,
+			ValueTag::Cons(u) | ValueTag::Func(u) |
+			ValueTag::Vector(u) | ValueTag::Table(u) |
+			ValueTag::Junk(u) => u+1,
 		}
 	}
 }
 
 pub type ValueStack<'a> = StackRef<'a, ValueTag>;
 
-#[derive(Debug)]
+/// walks the objects packed in a slice starting from the top (last) one
+/// stops early if a header claims more slots than are left
+pub struct Objects<'a>(&'a [ValueTag]);
+
+impl<'a> Iterator for Objects<'a> {
+	type Item = &'a [ValueTag];
+
+	fn next(&mut self) -> Option<&'a [ValueTag]> {
+		let size = self.0.last()?.get_size();
+		let start = self.0.len().checked_sub(size)?;
+		let (rest, obj) = self.0.split_at(start);
+		self.0 = rest;
+		Some(obj)
+	}
+}
+
+pub fn objects(slice:&[ValueTag]) -> Objects<'_> {
+	Objects(slice)
+}
+
+/// the payload of a list object
+pub fn list_items(obj:&[ValueTag]) -> Option<&[ValueTag]> {
+	match obj.split_last()? {
+		(ValueTag::Cons(n),items) if *n == items.len() => Some(items),
+		_ => None,
+	}
+}
+
+/// splits a packed sequence into its first object and the rest
+pub fn split_first(seq:&[ValueTag]) -> Option<(&[ValueTag],&[ValueTag])> {
+	let first = objects(seq).last()?;
+	Some(seq.split_at(first.len()))
+}
+
+/// the k-th object of a packed sequence counting from the bottom
+/// along with its offset
+pub fn nth_object(seq:&[ValueTag],k:usize,count:usize) -> (usize,&[ValueTag]) {
+	let mut end = seq.len();
+	let mut it = objects(seq);
+	for _ in k+1..count {
+		end -= it.next().map_or(0,|o| o.len());
+	}
+	let obj = it.next().unwrap_or(&[]);
+	(end-obj.len(),obj)
+}
+
+/// moves the top `keep` slots down to `start` dropping everything in between
+pub fn slide_down(stack:&mut ValueStack,start:usize,keep:usize){
+	let top = stack.write_index();
+	let (room,_) = stack.split();
+	room.copy_within(top-keep..top, start);
+	stack.flush(top-keep-start);
+}
+
+/// copies the slots [start,start+len) onto the top of the stack
+pub fn dup_range(stack:&mut ValueStack,start:usize,len:usize) -> Result<(),Error>{
+	if len == 0 {
+		return Ok(())
+	}
+
+	let (room,mut temp) = stack.split();
+	let end = start.checked_add(len).ok_or(Error::OutOfRange)?;
+	let src = room.get(start..end).ok_or(Error::OutOfRange)?;
+	temp.push_slice(src).map_err(|_| Error::StackOverflow)?;
+
+	//the copies now belong to the stack itself
+	mem::forget(temp);
+	unsafe { stack.advance(len) }
+	Ok(())
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
 pub enum Error {
 	StackOverflow,
+	StackUnderflow,
 	TypeError,
+	OutOfRange,
+	CapacityError,
+	/// a form did not fit the shape it was used in
+	SyntaxError,
+	/// nesting or rewriting went past a configured limit
+	DepthLimit,
+	UnboundSymbol,
+	/// resumed a coroutine that is already running or has finished
+	NotSuspended,
+	/// invoked an escape continuation after its call/ec returned
+	ExpiredEscape,
+	/// called with an argument count it does not accept
+	ArityError,
+	/// a native got an argument of the wrong type, counting from the first one
+	ArgTypeError(usize),
 }
 
+/// swaps the top two objects in place
 pub fn swap_things(stack:&mut ValueStack)-> Result<(),()>{
-	let (room,mut temp) = stack.split();
-	let room_raw = room as *mut [_];
-
-	let first = room.last().ok_or(())?;
-	let first = take_last(room,first.get_size());
-	
-	assert!(first.len() < room.len());
-
-	let second_size = take_last(room,first.len()+1)[0].get_size();
-	let second = &take_last(room,first.len()+second_size)[0..second_size];
-	temp.push_slice(second)?;
+	extract_objects(stack,1,1).map_err(|_| ())
+}
 
-	let room = take_last_raw(room_raw,second.len()+first.len())  as *mut ValueTag;
-	let first = first as *const [_];
-	let second = temp.peek_many(second.len()).unwrap() as *const [_];
+/// how many slots the top n objects take
+pub fn top_objects_len(stack:&ValueStack,n:usize) -> Result<usize,Error>{
+	let all = stack.peek_many(stack.write_index()).unwrap_or(&[]);
+	let mut it = objects(all);
+	let mut len = 0;
+	for _ in 0..n {
+		len += it.next().ok_or(Error::StackUnderflow)?.len();
+	}
+	Ok(len)
+}
 
-	unsafe{
-		ptr::copy(first as *const ValueTag,room,first.len());
-		ptr::copy_nonoverlapping(second as *const ValueTag,room.add(first.len()),second.len());
+/// pushes the packed objects in seq below the top skip objects
+pub fn insert_objects(stack:&mut ValueStack,skip:usize,seq:&[ValueTag]) -> Result<(),Error>{
+	if objects(seq).map(<[_]>::len).sum::<usize>() != seq.len() {
+		return Err(Error::TypeError)
 	}
+	let above = top_objects_len(stack,skip)?;
+	stack.insert_inside(above,seq).map_err(|_| Error::StackOverflow)
+}
 
-	Ok(())
+/// moves the count objects lying below the top skip objects onto the top
+/// everything happens in place so it works on a full stack
+pub fn extract_objects(stack:&mut ValueStack,skip:usize,count:usize) -> Result<(),Error>{
+	let above = top_objects_len(stack,skip)?;
+	let moved = top_objects_len(stack,skip+count)? - above;
+	stack.extract_inside(above,moved).map_err(|_| Error::StackUnderflow)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use ValueTag::*;
-    use crate::stack::{make_storage, StackRef};
+    use crate::array_stack::ArrayStack;
 
 
     /* 1. swap two single-element objects ................................... */
     #[test]
     fn swap_two_scalars() {
-    	let mut storage = make_storage::<_,4>();
-        let mut stack = StackRef::from_slice(&mut storage);
+    	let mut stack = ArrayStack::<_,4>::new();
 
         stack.push_slice(&[Int(1)]).unwrap();   // older  (lower)
         stack.push_slice(&[Int(2)]).unwrap();   // newer  (top)
 
-        swap_things(&mut stack).unwrap();
+        swap_things(&mut stack.view()).unwrap();
 
         assert_eq!(stack.pop(), Some(Int(1)));  // now top
         assert_eq!(stack.pop(), Some(Int(2)));
@@ -92,8 +208,7 @@ mod tests {
     /* 2. second is larger than first ....................................... */
     #[test]
     fn swap_vector_and_scalar() {
-        let mut storage = make_storage::<_,8>();
-        let mut stack = StackRef::from_slice(&mut storage);
+        let mut stack = ArrayStack::<_,8>::new();
 
         // second (older) = 2-element payload + tag
         stack.push_slice(&[Int(10), Int(11), Cons(2)]).unwrap();
@@ -101,7 +216,7 @@ mod tests {
         // first (newer)  = single Int
         stack.push_slice(&[Int(99)]).unwrap();
 
-        swap_things(&mut stack).unwrap();
+        swap_things(&mut stack.view()).unwrap();
 
         assert_eq!(stack.pop(), Some(Cons(2)));   // old second
         assert_eq!(stack.pop(), Some(Int(11)));
@@ -113,8 +228,7 @@ mod tests {
     /* 3. both variable-length .............................................. */
     #[test]
     fn swap_two_vectors() {
-        let mut storage = make_storage::<_,12>();
-        let mut stack = StackRef::from_slice(&mut storage);
+        let mut stack = ArrayStack::<_,12>::new();
 
         // second (older) – length 4
         stack.push_slice(&[Int(1), Int(2), Int(3), Cons(3)]).unwrap();
@@ -122,7 +236,7 @@ mod tests {
         // first (newer)  – length 3
         stack.push_slice(&[Float(4.0), Float(5.0), Cons(2)]).unwrap();
 
-        swap_things(&mut stack).unwrap();
+        swap_things(&mut stack.view()).unwrap();
 
         // Pop and check order
 
@@ -139,6 +253,35 @@ mod tests {
 
         assert_eq!(stack.pop(), None);
     }
+
+    #[test]
+    fn swap_needs_no_room() {
+        let mut stack = ArrayStack::<_,3>::new();
+        stack.push_slice(&[Int(1), Int(2), Cons(1)]).unwrap();
+
+        swap_things(&mut stack.view()).unwrap();
+        assert_eq!(stack.peek_many(3), Some(&[Int(2), Cons(1), Int(1)][..]));
+    }
+
+    #[test]
+    fn insert_and_extract_objects() {
+        let mut stack = ArrayStack::<_,12>::new();
+        stack.push_slice(&[Int(1), Int(2), Int(3), Cons(2), Nil]).unwrap();
+
+        // under the list and the nil
+        insert_objects(&mut stack.view(), 2, &[Char('a'), Bool(true), Cons(1)]).unwrap();
+        assert_eq!(stack.peek_many(8), Some(&[Int(1), Char('a'), Bool(true), Cons(1), Int(2), Int(3), Cons(2), Nil][..]));
+
+        // the char and the one element list go back on top
+        extract_objects(&mut stack.view(), 2, 2).unwrap();
+        assert_eq!(stack.peek_many(8), Some(&[Int(1), Int(2), Int(3), Cons(2), Nil, Char('a'), Bool(true), Cons(1)][..]));
+
+        assert_eq!(extract_objects(&mut stack.view(), 4, 2), Err(Error::StackUnderflow));
+        assert_eq!(insert_objects(&mut stack.view(), 6, &[Nil]), Err(Error::StackUnderflow));
+        assert_eq!(insert_objects(&mut stack.view(), 0, &[Nil, Cons(2)]), Err(Error::TypeError));
+        assert_eq!(insert_objects(&mut stack.view(), 1, &[Nil; 5]), Err(Error::StackOverflow));
+        assert_eq!(stack.len(), 8);
+    }
 }
 
 
@@ -251,4 +394,4 @@ mod tests {
 
 // 	todo!()
 // }
-// }
\ No newline at end of file
+// }
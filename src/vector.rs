@@ -0,0 +1,311 @@
+use crate::value::{dup_range, objects, Error, ValueStack, ValueTag};
+
+/*
+ * a vector is laid out like a list with its element count tucked under the header
+ *
+ * [e0 e1 .. en-1 Int(n) Vector(slots)]
+ *
+ * when every element is a single slot (slots == n+1) indexing is O(1)
+ * otherwise the elements are walked with get_size from the top
+ */
+
+/// the bounds of the vector sitting on top of a slice
+struct Layout {
+	/// index of the first slot of the vector
+	start: usize,
+	/// number of elements
+	len: usize,
+	/// slots taken by the elements
+	payload: usize,
+}
+
+fn top_vector(room:&[ValueTag],top:usize) -> Result<Layout,Error>{
+	let slots = match room.get(..top).and_then(|r| r.last()) {
+		None => return Err(Error::StackUnderflow),
+		Some(ValueTag::Vector(u)) if *u > 0 => *u,
+		Some(_) => return Err(Error::TypeError),
+	};
+
+	let start = top.checked_sub(slots+1).ok_or(Error::TypeError)?;
+	match room[top-2] {
+		ValueTag::Int(n) if n >= 0 && (n as usize) < slots => Ok(Layout{
+			start,
+			len: n as usize,
+			payload: slots-1,
+		}),
+		_ => Err(Error::TypeError),
+	}
+}
+
+/// finds the slots of element i of a vector
+fn element(room:&[ValueTag],v:&Layout,i:usize) -> Result<(usize,usize),Error>{
+	if i >= v.len {
+		return Err(Error::OutOfRange)
+	}
+
+	if v.payload == v.len {
+		return Ok((v.start+i,1))
+	}
+
+	let elems = &room[v.start..v.start+v.payload];
+	let mut end = elems.len();
+	for (n,obj) in objects(elems).enumerate() {
+		if n == v.len-1-i {
+			return Ok((v.start+end-obj.len(),obj.len()))
+		}
+		end -= obj.len();
+	}
+
+	Err(Error::TypeError)
+}
+
+/// where element i of the vector ending at top starts and how many slots it takes
+pub(crate) fn element_of(room:&[ValueTag],top:usize,i:usize) -> Result<(usize,usize),Error>{
+	let v = top_vector(room,top)?;
+	element(room,&v,i)
+}
+
+/// (make-vector k fill)
+/// stack: [Int(k) fill] -> [vector]
+pub fn make_vector(stack:&mut ValueStack) -> Result<(),Error>{
+	let fill_size = stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+	let k = match stack.peek_many(fill_size+1).map(|s| s[0]) {
+		None => return Err(Error::StackUnderflow),
+		Some(ValueTag::Int(k)) => usize::try_from(k).map_err(|_| Error::OutOfRange)?,
+		Some(_) => return Err(Error::TypeError),
+	};
+
+	let payload = k.checked_mul(fill_size).ok_or(Error::StackOverflow)?;
+	let needed = payload.checked_add(2).ok_or(Error::StackOverflow)?;
+	if needed > stack.room_left() + fill_size + 1 {
+		return Err(Error::StackOverflow)
+	}
+
+	let start = stack.write_index() - fill_size - 1;
+	if k == 0 {
+		stack.flush(fill_size+1);
+	} else {
+		//slide the fill over the count so it becomes element 0
+		let (room,_) = stack.split();
+		room.copy_within(start+1.., start);
+		stack.pop();
+
+		for _ in 1..k {
+			dup_range(stack,start,fill_size)?;
+		}
+	}
+
+	stack.push_n([ValueTag::Int(k as i64),ValueTag::Vector(payload+1)])
+		.map_err(|_| Error::StackOverflow)
+}
+
+/// (vector-length v)
+pub fn vector_length(stack:&mut ValueStack) -> Result<(),Error>{
+	let top = stack.write_index();
+	let (room,_) = stack.split();
+	let v = top_vector(room,top)?;
+
+	stack.flush(top-v.start);
+	stack.push(ValueTag::Int(v.len as i64)).map_err(|_| Error::StackOverflow)
+}
+
+/// (vector-ref v i)
+/// stack: [vector Int(i)] -> [element]
+pub fn vector_ref(stack:&mut ValueStack) -> Result<(),Error>{
+	let top = stack.write_index().checked_sub(1).ok_or(Error::StackUnderflow)?;
+	let i = match stack.peek() {
+		Some(ValueTag::Int(i)) => usize::try_from(*i).map_err(|_| Error::OutOfRange)?,
+		_ => return Err(Error::TypeError),
+	};
+
+	let (room,_) = stack.split();
+	let v = top_vector(room,top)?;
+	let (at,size) = element(room,&v,i)?;
+
+	room.copy_within(at..at+size, v.start);
+	stack.flush(top+1-v.start-size);
+	Ok(())
+}
+
+/// (vector-set! v i x)
+/// stack: [vector Int(i) x] -> [vector]
+/// x may have a different size than the element it replaces
+pub fn vector_set(stack:&mut ValueStack) -> Result<(),Error>{
+	let new_size = stack.peek().ok_or(Error::StackUnderflow)?.get_size();
+	let top = stack.write_index();
+	let vec_top = top.checked_sub(new_size+1).ok_or(Error::StackUnderflow)?;
+	let i = match stack.peek_many(new_size+1).map(|s| s[0]) {
+		Some(ValueTag::Int(i)) => usize::try_from(i).map_err(|_| Error::OutOfRange)?,
+		_ => return Err(Error::TypeError),
+	};
+
+	let (room,_) = stack.split();
+	let v = top_vector(room,vec_top)?;
+	let (at,old_size) = element(room,&v,i)?;
+
+	if old_size == new_size {
+		room.copy_within(top-new_size..top, at);
+		stack.flush(new_size+1);
+		return Ok(())
+	}
+
+	//[old rest len hdr idx new] -> [new old rest len hdr idx]
+	let span = &mut room[at..top];
+	span.rotate_right(new_size);
+
+	//-> [new rest len hdr]
+	let rest = vec_top-at-old_size;
+	span.copy_within(new_size+old_size..new_size+old_size+rest, new_size);
+
+	let payload = v.payload - old_size + new_size;
+	span[new_size+rest-1] = ValueTag::Vector(payload+1);
+	stack.flush(top-(at+new_size+rest));
+	Ok(())
+}
+
+/// (vector->list v)
+pub fn vector_to_list(stack:&mut ValueStack) -> Result<(),Error>{
+	let top = stack.write_index();
+	let (room,_) = stack.split();
+	let v = top_vector(room,top)?;
+
+	stack.flush(2);
+	stack.push(ValueTag::Cons(v.payload)).map_err(|_| Error::StackOverflow)
+}
+
+/// (list->vector l)
+pub fn list_to_vector(stack:&mut ValueStack) -> Result<(),Error>{
+	let slots = match stack.peek() {
+		None => return Err(Error::StackUnderflow),
+		Some(ValueTag::Cons(u)) => *u,
+		Some(_) => return Err(Error::TypeError),
+	};
+	if stack.room_left() == 0 {
+		return Err(Error::StackOverflow)
+	}
+
+	let elems = stack.peek_many(slots+1).ok_or(Error::TypeError)?;
+	let len = objects(&elems[..slots]).count();
+
+	stack.pop();
+	stack.push_n([ValueTag::Int(len as i64),ValueTag::Vector(slots+1)])
+		.map_err(|_| Error::StackOverflow)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ValueTag::*;
+	use crate::array_stack::ArrayStack;
+
+	fn nested(stack:&mut ValueStack){
+		//#(1 (2 3) 4)
+		stack.push_slice(&[Int(1),Int(2),Int(3),Cons(2),Int(4),Cons(5)]).unwrap();
+		list_to_vector(stack).unwrap();
+	}
+
+	#[test]
+	fn make_and_length() {
+		let mut stack = ArrayStack::<_,16>::new();
+
+		stack.push_slice(&[Int(3),Char('x')]).unwrap();
+		make_vector(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(5).unwrap(),&[Char('x'),Char('x'),Char('x'),Int(3),Vector(4)]);
+
+		vector_length(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(),Some(Int(3)));
+		assert_eq!(stack.pop(),None);
+
+		stack.push_slice(&[Int(2),Int(7),Int(8),Cons(2)]).unwrap();
+		make_vector(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(8).unwrap(),&[
+			Int(7),Int(8),Cons(2),Int(7),Int(8),Cons(2),Int(2),Vector(7)
+		]);
+		stack.flush_all();
+
+		stack.push_slice(&[Int(0),Int(7),Int(8),Cons(2)]).unwrap();
+		make_vector(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(2).unwrap(),&[Int(0),Vector(1)]);
+		assert_eq!(stack.len(),2);
+		stack.flush_all();
+
+		stack.push_slice(&[Int(20),Int(0)]).unwrap();
+		assert!(matches!(make_vector(&mut stack.view()),Err(Error::StackOverflow)));
+		assert_eq!(stack.len(),2);
+	}
+
+	#[test]
+	fn ref_flat_and_nested() {
+		let mut stack = ArrayStack::<_,16>::new();
+
+		stack.push_slice(&[Int(5),Int(6),Int(2),Vector(3),Int(1)]).unwrap();
+		vector_ref(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(),Some(Int(6)));
+		assert_eq!(stack.pop(),None);
+
+		nested(&mut stack.view());
+		stack.push(Int(1)).unwrap();
+		vector_ref(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(3).unwrap(),&[Int(2),Int(3),Cons(2)]);
+		assert_eq!(stack.len(),3);
+		stack.flush_all();
+
+		for (i,want) in [(0,1),(2,4)] {
+			nested(&mut stack.view());
+			stack.push(Int(i)).unwrap();
+			vector_ref(&mut stack.view()).unwrap();
+			assert_eq!(stack.pop(),Some(Int(want)));
+			assert_eq!(stack.pop(),None);
+		}
+
+		nested(&mut stack.view());
+		stack.push(Int(3)).unwrap();
+		assert!(matches!(vector_ref(&mut stack.view()),Err(Error::OutOfRange)));
+	}
+
+	#[test]
+	fn set_resizes() {
+		let mut stack = ArrayStack::<_,16>::new();
+
+		//same size
+		nested(&mut stack.view());
+		stack.push_slice(&[Int(0),Int(9)]).unwrap();
+		vector_set(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(7).unwrap(),&[Int(9),Int(2),Int(3),Cons(2),Int(4),Int(3),Vector(6)]);
+
+		//grow element 0
+		stack.push_slice(&[Int(0),Int(7),Int(8),Cons(2)]).unwrap();
+		vector_set(&mut stack.view()).unwrap();
+		assert_eq!(stack.len(),9);
+		assert_eq!(stack.peek_many(9).unwrap(),&[
+			Int(7),Int(8),Cons(2),Int(2),Int(3),Cons(2),Int(4),Int(3),Vector(8)
+		]);
+
+		//shrink element 1
+		stack.push_slice(&[Int(1),Nil]).unwrap();
+		vector_set(&mut stack.view()).unwrap();
+		assert_eq!(stack.len(),7);
+		assert_eq!(stack.peek_many(7).unwrap(),&[
+			Int(7),Int(8),Cons(2),Nil,Int(4),Int(3),Vector(6)
+		]);
+
+		stack.push_slice(&[Int(2)]).unwrap();
+		vector_ref(&mut stack.view()).unwrap();
+		assert_eq!(stack.pop(),Some(Int(4)));
+	}
+
+	#[test]
+	fn list_round_trip() {
+		let mut stack = ArrayStack::<_,16>::new();
+
+		nested(&mut stack.view());
+		assert_eq!(stack.peek_many(2).unwrap(),&[Int(3),Vector(6)]);
+
+		vector_to_list(&mut stack.view()).unwrap();
+		assert_eq!(stack.peek_many(6).unwrap(),&[Int(1),Int(2),Int(3),Cons(2),Int(4),Cons(5)]);
+		assert_eq!(stack.len(),6);
+
+		stack.push(Int(1)).unwrap();
+		assert!(matches!(vector_to_list(&mut stack.view()),Err(Error::TypeError)));
+	}
+}